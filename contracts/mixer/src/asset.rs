@@ -0,0 +1,76 @@
+use std::fmt;
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{to_binary, Addr, Api, BankMsg, Coin, CosmosMsg, StdResult, Uint128, WasmMsg};
+
+use crate::error::ContractError;
+use crate::msg::Cw20ExecuteMsg;
+
+/// AssetInfo, the asset a pool takes in on deposit and pays out on withdraw
+#[cw_serde]
+pub enum AssetInfo {
+    Token { contract_addr: Addr },
+    NativeToken { denom: String },
+}
+
+/// AssetInfo as given in messages, the cw20 address is not validated yet
+#[cw_serde]
+pub enum AssetInfoMsg {
+    Token { contract_addr: String },
+    NativeToken { denom: String },
+}
+
+impl AssetInfoMsg {
+    pub fn validate(self, api: &dyn Api) -> Result<AssetInfo, ContractError> {
+        match self {
+            AssetInfoMsg::Token { contract_addr } => Ok(AssetInfo::Token {
+                contract_addr: api.addr_validate(&contract_addr).map_err(|_| {
+                    ContractError::InvalidAddress {
+                        address: contract_addr,
+                    }
+                })?,
+            }),
+            AssetInfoMsg::NativeToken { denom } => Ok(AssetInfo::NativeToken { denom }),
+        }
+    }
+}
+
+impl From<AssetInfo> for AssetInfoMsg {
+    fn from(asset: AssetInfo) -> Self {
+        match asset {
+            AssetInfo::Token { contract_addr } => AssetInfoMsg::Token {
+                contract_addr: contract_addr.into_string(),
+            },
+            AssetInfo::NativeToken { denom } => AssetInfoMsg::NativeToken { denom },
+        }
+    }
+}
+
+impl fmt::Display for AssetInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetInfo::NativeToken { denom } => write!(f, "{}", denom),
+            AssetInfo::Token { contract_addr } => write!(f, "{}", contract_addr),
+        }
+    }
+}
+
+impl AssetInfo {
+    /// Build the message sending `amount` of this asset to `recipient`
+    pub fn transfer_msg(&self, recipient: String, amount: Uint128) -> StdResult<CosmosMsg> {
+        match self {
+            AssetInfo::NativeToken { denom } => Ok(CosmosMsg::Bank(BankMsg::Send {
+                to_address: recipient,
+                amount: vec![Coin {
+                    denom: denom.clone(),
+                    amount,
+                }],
+            })),
+            AssetInfo::Token { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer { recipient, amount })?,
+                funds: vec![],
+            })),
+        }
+    }
+}
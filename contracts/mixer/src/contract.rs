@@ -1,26 +1,53 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, entry_point, to_binary, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env, Event,
-    MessageInfo, Response, StdError, StdResult,
+    attr, from_binary, to_binary, Addr, Api, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env,
+    Event, MessageInfo, Response, StdError, StdResult, Storage, Uint128,
 };
 
+use crate::asset::AssetInfo;
 use crate::error::ContractError;
+use crate::migration::migrate_from_v0_1;
 use crate::msg::{
-    ConfigResponse, DepositMsg, ExecuteMsg, InstantiateMsg, MerkleRootResponse,
-    MerkleTreeInfoResponse, QueryMsg, WithdrawMsg,
+    AreSpentResponse, BatchDepositMsg, CommitmentIndexResponse, CommitmentResponse,
+    CommitmentsResponse, ConfigResponse, Curve, Cw20HookMsg, Cw20ReceiveMsg, DepositMsg,
+    ExcessFunds, ExecuteMsg, InstantiateMsg, IsKnownRootResponse, IsSpentResponse,
+    MerklePathResponse, MerkleRootResponse, MerkleTreeInfoResponse, MigrateMsg, PauseTarget,
+    PoolResponse, PoolsResponse, ProtocolFeeMsg, ProtocolFeeRate, ProtocolFeeResponse, QueryMsg,
+    RelayerPolicyResponse, RelayerResponse, RelayersResponse, RootHistoryEntry,
+    RootHistoryResponse, StatusResponse, VerifyingKeyResponse, WithdrawMsg,
 };
 
-use crate::utils::{element_encoder, truncate_and_pad};
-use crate::zeroes::zeroes;
+use crate::utils::{
+    element_encoder, encode_address, encode_domain, encode_expiry, is_canonical, sha256,
+};
+use crate::zeroes::DEFAULT_LEAF;
 
 use crate::state::{
-    mixer_read, mixer_write, nullifier_read, nullifier_write, read_root, save_root, save_subtree,
-    MerkleTree, Mixer,
+    config_read, config_write, epoch_tree_read, get_contract_version, is_deposited, mixer_read,
+    mixer_write, mixers_read, nullifier_read, nullifier_write, pause_status_read,
+    pause_status_write, read_commitment_index, read_commitments, read_node, read_root,
+    read_root_info, read_zero, relayer_policy_read, relayer_policy_write, relayer_read,
+    relayer_remove, relayer_stats_read, relayer_stats_write, relayer_write, relayers_read,
+    set_contract_version, Config, MerkleTree, Mixer, PendingVerifyingKey, ProtocolFee, Relayer,
+    DEFAULT_REFUND_DENOM, DEFAULT_ROOT_HISTORY_SIZE, MAX_FEE_BPS, MAX_MERKLE_TREE_LEVELS,
+    MAX_PROTOCOL_FEE_BPS, MAX_ROOT_HISTORY_SIZE, MAX_VK_UPDATE_DELAY,
 };
 
-#[entry_point]
+// version info for migration info
+const CONTRACT_NAME: &str = "crates.io:cosmwasm-mixer";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// settings for pagination
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+const MAX_METADATA_URL_LENGTH: usize = 256;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
@@ -29,128 +56,867 @@ pub fn instantiate(
         return Err(ContractError::UnnecessaryFunds {});
     }
 
-    // Initialize the "Mixer"
-    let merkle_tree: MerkleTree = MerkleTree {
-        levels: msg.merkletree_levels,
-        curve: msg.curve,
-        current_root_index: 0,
-        next_index: 0,
+    let root_history_size = msg.root_history_size.unwrap_or(DEFAULT_ROOT_HISTORY_SIZE);
+    assert_root_history_size(root_history_size)?;
+    assert_vk_update_delay(msg.vk_update_delay)?;
+    // pools can not be added later
+    if msg.pools.is_empty() {
+        return Err(ContractError::NoPools {});
+    }
+    if msg.merkletree_levels == 0 || msg.merkletree_levels > MAX_MERKLE_TREE_LEVELS {
+        return Err(ContractError::InvalidMerkleTreeLevels {
+            levels: msg.merkletree_levels,
+            max: MAX_MERKLE_TREE_LEVELS,
+        });
+    }
+    let zero_leaf = match msg.zero_leaf {
+        Some(zero_leaf) if zero_leaf.len() != 32 => return Err(ContractError::InvalidZeroLeaf {}),
+        Some(zero_leaf) => element_encoder(&zero_leaf),
+        None => DEFAULT_LEAF,
     };
-    let native_token_denom = msg.native_token_denom;
+    // the prover hashes the same field element, it must not be reduced
+    if !is_canonical(&zero_leaf, msg.curve) {
+        return Err(ContractError::InvalidZeroLeaf {});
+    }
 
-    let deposit_size = msg.deposit_size;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-    let mixer: Mixer = Mixer {
-        native_token_denom,
-        deposit_size,
-        merkle_tree,
-        vk_raw: msg.vk_raw,
+    let admin = match msg.admin {
+        Some(admin) => validate_addr(deps.api, &admin)?,
+        None => info.sender,
     };
-    mixer_write(deps.storage, &mixer)?;
 
-    for i in 0..msg.merkletree_levels {
-        save_subtree(deps.storage, i as u32, &zeroes(i));
-    }
+    config_write(
+        deps.storage,
+        &Config {
+            admin: admin.clone(),
+            pending_admin: None,
+            vk_raw: msg.vk_raw,
+            vk_update_delay: msg.vk_update_delay,
+            pending_vk: None,
+            excess_funds: msg.excess_funds.unwrap_or_default(),
+            refund_denom: msg
+                .refund_denom
+                .unwrap_or_else(|| DEFAULT_REFUND_DENOM.to_string()),
+            protocol_fee: None,
+        },
+    )?;
 
-    save_root(deps.storage, 0_u32, &zeroes(msg.merkletree_levels));
+    // Initialize the "Mixer" pools
+    for (pool_id, pool) in msg.pools.into_iter().enumerate() {
+        let pool_id = pool_id as u32;
+
+        // Validation 2. Check if the cw20 token address is valid
+        let asset = pool.asset.validate(deps.api)?;
+
+        let merkle_tree: MerkleTree = MerkleTree {
+            levels: msg.merkletree_levels,
+            curve: msg.curve.id(),
+            current_root_index: 0,
+            next_index: 0,
+            epoch: 0,
+            root_history_size,
+        };
+        merkle_tree.init_zeroes(deps.api, pool_id, zero_leaf, deps.storage)?;
+        merkle_tree.init(pool_id, &env.block, deps.storage)?;
+
+        let mixer: Mixer = Mixer {
+            asset,
+            deposit_size: pool.deposit_size,
+            merkle_tree,
+        };
+        mixer_write(deps.storage, pool_id, &mixer)?;
+    }
 
     Ok(Response::new()
         .add_attribute("action", "instantiate")
-        .add_attribute("owner", info.sender))
+        .add_attribute("owner", admin))
 }
 
-#[entry_point]
+#[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
         // Deposit the "native" tokens with commitment
-        ExecuteMsg::Deposit(msg) => deposit(deps, info, msg),
-        // Withdraw either "native" tokens
-        ExecuteMsg::Withdraw(msg) => withdraw(deps, info, msg),
+        ExecuteMsg::Deposit(msg) => deposit(deps, env, info, msg),
+        ExecuteMsg::BatchDeposit(msg) => batch_deposit(deps, env, info, msg),
+        // Withdraw either "native" or "cw20" tokens
+        ExecuteMsg::Withdraw(msg) => withdraw(deps, env, info, msg),
+        // Deposit the "cw20" tokens with commitment
+        ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
+        ExecuteMsg::ProposeAdmin { admin } => propose_admin(deps, info, admin),
+        ExecuteMsg::AcceptAdmin {} => accept_admin(deps, info),
+        ExecuteMsg::Pause { target, reason } => pause(deps, info, target, reason),
+        ExecuteMsg::Unpause { target } => unpause(deps, info, target),
+        ExecuteMsg::ProposeVerifyingKey { vk_raw } => {
+            propose_verifying_key(deps, env, info, vk_raw)
+        }
+        ExecuteMsg::CancelVerifyingKey {} => cancel_verifying_key(deps, info),
+        ExecuteMsg::ApplyVerifyingKey {} => apply_verifying_key(deps, env),
+        ExecuteMsg::RegisterRelayer {
+            fee_bps,
+            metadata_url,
+        } => register_relayer(deps, info, fee_bps, metadata_url),
+        ExecuteMsg::UnregisterRelayer {} => unregister_relayer(deps, info),
+        ExecuteMsg::UpdateProtocolFee { protocol_fee } => {
+            update_protocol_fee(deps, info, protocol_fee)
+        }
+        ExecuteMsg::UpdateRelayerPolicy {
+            registration_required,
+            max_fee_bps,
+        } => update_relayer_policy(deps, info, registration_required, max_fee_bps),
+    }
+}
+
+fn assert_root_history_size(size: u32) -> Result<(), ContractError> {
+    if size == 0 || size > MAX_ROOT_HISTORY_SIZE {
+        return Err(ContractError::InvalidRootHistorySize {
+            size,
+            max: MAX_ROOT_HISTORY_SIZE,
+        });
+    }
+    Ok(())
+}
+
+fn assert_vk_update_delay(delay: u64) -> Result<(), ContractError> {
+    if delay > MAX_VK_UPDATE_DELAY {
+        return Err(ContractError::InvalidVkUpdateDelay {
+            delay,
+            max: MAX_VK_UPDATE_DELAY,
+        });
+    }
+    Ok(())
+}
+
+/// Addresses given in messages, an invalid one is reported by value
+fn validate_addr(api: &dyn Api, address: &str) -> Result<Addr, ContractError> {
+    api.addr_validate(address)
+        .map_err(|_| ContractError::InvalidAddress {
+            address: address.to_string(),
+        })
+}
+
+fn assert_admin(config: &Config, info: &MessageInfo) -> Result<(), ContractError> {
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+fn assert_deposits_not_paused(deps: Deps) -> Result<(), ContractError> {
+    let status = pause_status_read(deps.storage)?;
+    if status.deposits_paused {
+        return Err(ContractError::DepositsPaused {
+            reason: status.reason.unwrap_or_default(),
+        });
     }
+    Ok(())
+}
+
+pub fn propose_admin(
+    deps: DepsMut,
+    info: MessageInfo,
+    admin: String,
+) -> Result<Response, ContractError> {
+    let mut config = config_read(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    let pending_admin = validate_addr(deps.api, &admin)?;
+    config.pending_admin = Some(pending_admin.clone());
+    config_write(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "propose_admin"),
+        attr("pending_admin", pending_admin),
+    ]))
+}
+
+pub fn accept_admin(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = config_read(deps.storage)?;
+    if config.pending_admin.as_ref() != Some(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    config.admin = info.sender;
+    config.pending_admin = None;
+    config_write(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "accept_admin"),
+        attr("admin", config.admin),
+    ]))
+}
+
+pub fn pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    target: PauseTarget,
+    reason: String,
+) -> Result<Response, ContractError> {
+    let config = config_read(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    let mut status = pause_status_read(deps.storage)?;
+    match target {
+        PauseTarget::Deposits => status.deposits_paused = true,
+        PauseTarget::Withdrawals => status.withdrawals_paused = true,
+        PauseTarget::All => {
+            status.deposits_paused = true;
+            status.withdrawals_paused = true;
+        }
+    }
+    status.reason = Some(reason.clone());
+    pause_status_write(deps.storage, &status)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "pause"),
+        attr("deposits_paused", status.deposits_paused.to_string()),
+        attr("withdrawals_paused", status.withdrawals_paused.to_string()),
+        attr("reason", reason),
+    ]))
+}
+
+pub fn unpause(
+    deps: DepsMut,
+    info: MessageInfo,
+    target: PauseTarget,
+) -> Result<Response, ContractError> {
+    let config = config_read(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    let mut status = pause_status_read(deps.storage)?;
+    match target {
+        PauseTarget::Deposits => status.deposits_paused = false,
+        PauseTarget::Withdrawals => status.withdrawals_paused = false,
+        PauseTarget::All => {
+            status.deposits_paused = false;
+            status.withdrawals_paused = false;
+        }
+    }
+    // Keep the reason while anything is still paused
+    if !status.deposits_paused && !status.withdrawals_paused {
+        status.reason = None;
+    }
+    pause_status_write(deps.storage, &status)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "unpause"),
+        attr("deposits_paused", status.deposits_paused.to_string()),
+        attr("withdrawals_paused", status.withdrawals_paused.to_string()),
+    ]))
+}
+
+pub fn propose_verifying_key(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vk_raw: Binary,
+) -> Result<Response, ContractError> {
+    let mut config = config_read(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    let activates_at = env
+        .block
+        .time
+        .seconds()
+        .checked_add(config.vk_update_delay)
+        .ok_or(ContractError::InvalidVkUpdateDelay {
+            delay: config.vk_update_delay,
+            max: MAX_VK_UPDATE_DELAY,
+        })?;
+    let vk_hash = Binary::from(sha256(&vk_raw));
+    config.pending_vk = Some(PendingVerifyingKey {
+        vk_raw,
+        activates_at,
+    });
+    config_write(deps.storage, &config)?;
+
+    Ok(
+        Response::new().add_event(Event::new("mixer-vk-update").add_attributes(vec![
+            attr("action", "propose_verifying_key"),
+            attr("pending_vk_hash", vk_hash.to_base64()),
+            attr("activates_at", activates_at.to_string()),
+        ])),
+    )
+}
+
+pub fn cancel_verifying_key(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = config_read(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    let pending_vk = config
+        .pending_vk
+        .take()
+        .ok_or(ContractError::NoPendingVerifyingKey {})?;
+    config_write(deps.storage, &config)?;
+
+    Ok(
+        Response::new().add_event(Event::new("mixer-vk-update").add_attributes(vec![
+            attr("action", "cancel_verifying_key"),
+            attr(
+                "pending_vk_hash",
+                Binary::from(sha256(&pending_vk.vk_raw)).to_base64(),
+            ),
+        ])),
+    )
+}
+
+pub fn apply_verifying_key(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let mut config = config_read(deps.storage)?;
+
+    let pending_vk = config
+        .pending_vk
+        .take()
+        .ok_or(ContractError::NoPendingVerifyingKey {})?;
+    if env.block.time.seconds() < pending_vk.activates_at {
+        return Err(ContractError::VerifyingKeyTimelocked {
+            activates_at: pending_vk.activates_at,
+        });
+    }
+
+    let previous_vk_hash = Binary::from(sha256(&config.vk_raw));
+    config.vk_raw = pending_vk.vk_raw;
+    config_write(deps.storage, &config)?;
+
+    Ok(
+        Response::new().add_event(Event::new("mixer-vk-update").add_attributes(vec![
+            attr("action", "apply_verifying_key"),
+            attr("previous_vk_hash", previous_vk_hash.to_base64()),
+            attr("vk_hash", Binary::from(sha256(&config.vk_raw)).to_base64()),
+        ])),
+    )
+}
+
+pub fn update_protocol_fee(
+    deps: DepsMut,
+    info: MessageInfo,
+    protocol_fee: Option<ProtocolFeeMsg>,
+) -> Result<Response, ContractError> {
+    let mut config = config_read(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    config.protocol_fee = match protocol_fee {
+        Some(protocol_fee) => {
+            let protocol_fee = ProtocolFee {
+                rate: protocol_fee.rate,
+                treasury: validate_addr(deps.api, &protocol_fee.treasury)?,
+            };
+            for (pool_id, mixer) in mixers_read(deps.storage)? {
+                let fee = protocol_fee.amount(mixer.deposit_size);
+                let max = mixer
+                    .deposit_size
+                    .multiply_ratio(MAX_PROTOCOL_FEE_BPS, MAX_FEE_BPS);
+                if fee > max {
+                    return Err(ContractError::ProtocolFeeTooHigh { pool_id, fee, max });
+                }
+            }
+            Some(protocol_fee)
+        }
+        None => None,
+    };
+    config_write(deps.storage, &config)?;
+
+    let mut attributes = vec![attr("action", "update_protocol_fee")];
+    if let Some(protocol_fee) = config.protocol_fee {
+        attributes.push(attr("rate", format_rate(protocol_fee.rate)));
+        attributes.push(attr("treasury", protocol_fee.treasury));
+    }
+    Ok(Response::new().add_event(Event::new("mixer-protocol-fee").add_attributes(attributes)))
+}
+
+fn format_rate(rate: ProtocolFeeRate) -> String {
+    match rate {
+        ProtocolFeeRate::Bps(bps) => format!("{}bps", bps),
+        ProtocolFeeRate::Flat(amount) => amount.to_string(),
+    }
+}
+
+pub fn register_relayer(
+    deps: DepsMut,
+    info: MessageInfo,
+    fee_bps: u16,
+    metadata_url: String,
+) -> Result<Response, ContractError> {
+    let policy = relayer_policy_read(deps.storage)?;
+    if fee_bps > policy.max_fee_bps {
+        return Err(ContractError::FeeBpsTooHigh {
+            fee_bps,
+            max: policy.max_fee_bps,
+        });
+    }
+    if metadata_url.len() > MAX_METADATA_URL_LENGTH {
+        return Err(ContractError::InvalidMetadataUrl {
+            max: MAX_METADATA_URL_LENGTH,
+        });
+    }
+    relayer_write(
+        deps.storage,
+        &info.sender,
+        &Relayer {
+            fee_bps,
+            metadata_url: metadata_url.clone(),
+        },
+    )?;
+
+    Ok(
+        Response::new().add_event(Event::new("mixer-relayer").add_attributes(vec![
+            attr("action", "register_relayer"),
+            attr("relayer", info.sender),
+            attr("fee_bps", fee_bps.to_string()),
+            attr("metadata_url", metadata_url),
+        ])),
+    )
+}
+
+pub fn unregister_relayer(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    if relayer_read(deps.storage, &info.sender)?.is_none() {
+        return Err(ContractError::RelayerNotRegistered {});
+    }
+    relayer_remove(deps.storage, &info.sender);
+
+    Ok(
+        Response::new().add_event(Event::new("mixer-relayer").add_attributes(vec![
+            attr("action", "unregister_relayer"),
+            attr("relayer", info.sender),
+        ])),
+    )
+}
+
+pub fn update_relayer_policy(
+    deps: DepsMut,
+    info: MessageInfo,
+    registration_required: Option<bool>,
+    max_fee_bps: Option<u16>,
+) -> Result<Response, ContractError> {
+    let config = config_read(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    let mut policy = relayer_policy_read(deps.storage)?;
+    if let Some(registration_required) = registration_required {
+        policy.registration_required = registration_required;
+    }
+    if let Some(max_fee_bps) = max_fee_bps {
+        if max_fee_bps > MAX_FEE_BPS {
+            return Err(ContractError::FeeBpsTooHigh {
+                fee_bps: max_fee_bps,
+                max: MAX_FEE_BPS,
+            });
+        }
+        policy.max_fee_bps = max_fee_bps;
+    }
+    relayer_policy_write(deps.storage, &policy)?;
+
+    Ok(
+        Response::new().add_event(Event::new("mixer-relayer").add_attributes(vec![
+            attr("action", "update_relayer_policy"),
+            attr(
+                "registration_required",
+                policy.registration_required.to_string(),
+            ),
+            attr("max_fee_bps", policy.max_fee_bps.to_string()),
+        ])),
+    )
+}
+
+/// Registered relayers are held to their declared fee, every relayer to the policy cap
+fn assert_relayer_fee(
+    storage: &dyn Storage,
+    relayer: &Addr,
+    fee: Uint128,
+    deposit_size: Uint128,
+) -> Result<(), ContractError> {
+    let policy = relayer_policy_read(storage)?;
+    let fee_bps = match relayer_read(storage, relayer)? {
+        Some(registered) => registered.fee_bps.min(policy.max_fee_bps),
+        None if policy.registration_required => {
+            return Err(ContractError::UnregisteredRelayer {
+                address: relayer.to_string(),
+            })
+        }
+        None => policy.max_fee_bps,
+    };
+    let max = deposit_size.multiply_ratio(fee_bps, MAX_FEE_BPS);
+    if fee > max {
+        return Err(ContractError::RelayerFeeTooHigh { fee, max });
+    }
+    Ok(())
 }
 
 pub fn deposit(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     msg: DepositMsg,
 ) -> Result<Response, ContractError> {
-    let mut mixer = mixer_read(deps.storage)?;
+    assert_deposits_not_paused(deps.as_ref())?;
 
-    let sent_tokens: Vec<Coin> = info
-        .funds
-        .into_iter()
-        .filter(|x| x.denom == mixer.native_token_denom)
-        .collect();
-    if sent_tokens.is_empty() || sent_tokens[0].amount < mixer.deposit_size {
+    let mixer = load_mixer(deps.storage, msg.pool_id)?;
+
+    // cw20 pools only take deposits through the "Receive" hook
+    let denom = match &mixer.asset {
+        AssetInfo::NativeToken { denom } => denom,
+        AssetInfo::Token { .. } => return Err(ContractError::InvalidAsset {}),
+    };
+
+    let excess_funds = config_read(deps.storage)?.excess_funds;
+    let refund = native_refund(excess_funds, denom, mixer.deposit_size, info.funds)?;
+
+    let response = insert_commitment(deps, env, mixer, msg)?;
+    Ok(add_refund(response, info.sender.to_string(), refund))
+}
+
+pub fn batch_deposit(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: BatchDepositMsg,
+) -> Result<Response, ContractError> {
+    assert_deposits_not_paused(deps.as_ref())?;
+
+    let mixer = load_mixer(deps.storage, msg.pool_id)?;
+
+    let denom = match &mixer.asset {
+        AssetInfo::NativeToken { denom } => denom,
+        AssetInfo::Token { .. } => return Err(ContractError::InvalidAsset {}),
+    };
+
+    let expected = batch_amount(&mixer, msg.commitments.len())?;
+    let excess_funds = config_read(deps.storage)?.excess_funds;
+    let refund = native_refund(excess_funds, denom, expected, info.funds)?;
+
+    let response = insert_commitments(deps, env, mixer, msg)?;
+    Ok(add_refund(response, info.sender.to_string(), refund))
+}
+
+pub fn receive_cw20(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let hook_msg = from_binary(&cw20_msg.msg).map_err(|_| ContractError::InvalidHookMsg {})?;
+    match hook_msg {
+        Cw20HookMsg::Deposit(msg) => {
+            assert_deposits_not_paused(deps.as_ref())?;
+
+            let mixer = load_mixer(deps.storage, msg.pool_id)?;
+            assert_pool_token(&mixer, &info)?;
+
+            let excess_funds = config_read(deps.storage)?.excess_funds;
+            let excess = excess_amount(excess_funds, mixer.deposit_size, cw20_msg.amount)?;
+            let refund = cw20_refund(&mixer, &cw20_msg.sender, excess)?;
+
+            let response = insert_commitment(deps, env, mixer, msg)?;
+            Ok(response.add_messages(refund))
+        }
+        Cw20HookMsg::BatchDeposit(msg) => {
+            assert_deposits_not_paused(deps.as_ref())?;
+
+            let mixer = load_mixer(deps.storage, msg.pool_id)?;
+            assert_pool_token(&mixer, &info)?;
+
+            let expected = batch_amount(&mixer, msg.commitments.len())?;
+            let excess_funds = config_read(deps.storage)?.excess_funds;
+            let excess = excess_amount(excess_funds, expected, cw20_msg.amount)?;
+            let refund = cw20_refund(&mixer, &cw20_msg.sender, excess)?;
+
+            let response = insert_commitments(deps, env, mixer, msg)?;
+            Ok(response.add_messages(refund))
+        }
+    }
+}
+
+// Only the pool token contract can call the hook
+fn assert_pool_token(mixer: &Mixer, info: &MessageInfo) -> Result<(), ContractError> {
+    match &mixer.asset {
+        AssetInfo::Token { contract_addr } if contract_addr == &info.sender => Ok(()),
+        _ => Err(ContractError::Unauthorized {}),
+    }
+}
+
+/// Amount sent beyond `expected`, which only the `Refund` mode accepts
+fn excess_amount(
+    excess_funds: ExcessFunds,
+    expected: Uint128,
+    received: Uint128,
+) -> Result<Uint128, ContractError> {
+    if received < expected {
         return Err(ContractError::InsufficientFunds {});
     }
+    if received > expected && excess_funds == ExcessFunds::Reject {
+        return Err(ContractError::InvalidDepositAmount { expected, received });
+    }
+    Ok(received - expected)
+}
+
+/// Coins to send back to a native depositor, the excess of the pool denom and every other denom
+fn native_refund(
+    excess_funds: ExcessFunds,
+    denom: &str,
+    expected: Uint128,
+    funds: Vec<Coin>,
+) -> Result<Vec<Coin>, ContractError> {
+    let received: Uint128 = funds
+        .iter()
+        .filter(|x| x.denom == denom)
+        .map(|x| x.amount)
+        .sum();
+    let excess = excess_amount(excess_funds, expected, received)?;
+
+    let mut refund = vec![];
+    if !excess.is_zero() {
+        refund.push(Coin::new(excess.u128(), denom));
+    }
+    for coin in funds {
+        if coin.denom == denom || coin.amount.is_zero() {
+            continue;
+        }
+        if excess_funds == ExcessFunds::Reject {
+            return Err(ContractError::UnexpectedDenom { denom: coin.denom });
+        }
+        refund.push(coin);
+    }
+    Ok(refund)
+}
+
+fn add_refund(response: Response, depositor: String, refund: Vec<Coin>) -> Response {
+    if refund.is_empty() {
+        return response;
+    }
+    response.add_message(BankMsg::Send {
+        to_address: depositor,
+        amount: refund,
+    })
+}
+
+fn cw20_refund(
+    mixer: &Mixer,
+    depositor: &str,
+    excess: Uint128,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    if excess.is_zero() {
+        return Ok(vec![]);
+    }
+    Ok(vec![mixer
+        .asset
+        .transfer_msg(depositor.to_string(), excess)?])
+}
+
+fn batch_amount(mixer: &Mixer, count: usize) -> Result<Uint128, ContractError> {
+    if count == 0 {
+        return Err(ContractError::EmptyBatch {});
+    }
+    mixer
+        .deposit_size
+        .checked_mul(Uint128::from(count as u128))
+        .map_err(|_| ContractError::BatchTooLarge { count })
+}
+
+/// The pool of an execute message, a missing pool is reported by id
+fn load_mixer(storage: &dyn Storage, pool_id: u32) -> Result<Mixer, ContractError> {
+    mixer_read(storage, pool_id).map_err(|err| match err {
+        StdError::NotFound { .. } => ContractError::UnknownPool { pool_id },
+        err => err.into(),
+    })
+}
+
+/// A commitment is a canonical field element of the pool curve that is not deposited yet
+fn validate_commitment(
+    storage: &dyn Storage,
+    pool_id: u32,
+    merkle_tree: &MerkleTree,
+    commitment: &Binary,
+) -> Result<[u8; 32], ContractError> {
+    if commitment.len() != 32 {
+        return Err(ContractError::InvalidCommitmentLength {
+            length: commitment.len(),
+        });
+    }
+    let commitment_bytes = element_encoder(commitment.as_slice());
+    let curve = Curve::try_from(merkle_tree.curve).map_err(|_| ContractError::InvalidCurve {
+        id: merkle_tree.curve,
+    })?;
+    if !is_canonical(&commitment_bytes, curve) {
+        return Err(ContractError::NonCanonicalCommitment {});
+    }
+    if is_deposited(storage, pool_id, &commitment_bytes) {
+        return Err(ContractError::DuplicateCommitment {});
+    }
+    Ok(commitment_bytes)
+}
 
+fn insert_commitment(
+    deps: DepsMut,
+    env: Env,
+    mut mixer: Mixer,
+    msg: DepositMsg,
+) -> Result<Response, ContractError> {
     // Handle the "deposit"
-    let commitment_bytes = element_encoder(msg.commitment.as_slice());
+    let commitment_bytes = validate_commitment(
+        deps.storage,
+        msg.pool_id,
+        &mixer.merkle_tree,
+        &msg.commitment,
+    )?;
 
     // insert commitment into merke_tree
-    let inserted_index = mixer
-        .merkle_tree
-        .insert(deps.api, commitment_bytes, deps.storage)?;
-    mixer_write(deps.storage, &mixer)?;
-    return Ok(
+    let inserted_index = mixer.merkle_tree.insert(
+        deps.api,
+        msg.pool_id,
+        commitment_bytes,
+        &env.block,
+        deps.storage,
+    )?;
+    mixer_write(deps.storage, msg.pool_id, &mixer)?;
+    Ok(
         Response::new().add_event(Event::new("mixer-deposit").add_attributes(vec![
             attr("action", "deposit"),
+            attr("pool_id", msg.pool_id.to_string()),
+            attr("epoch", mixer.merkle_tree.epoch.to_string()),
             attr("inserted_index", inserted_index.to_string()),
             attr("commitment", msg.commitment.to_base64()),
         ])),
-    );
+    )
+}
+
+fn insert_commitments(
+    deps: DepsMut,
+    env: Env,
+    mut mixer: Mixer,
+    msg: BatchDepositMsg,
+) -> Result<Response, ContractError> {
+    let mut inserted_indices = Vec::with_capacity(msg.commitments.len());
+    // a batch can roll the tree over, so each index is paired with its epoch
+    let mut epochs = Vec::with_capacity(msg.commitments.len());
+    for commitment in msg.commitments.iter() {
+        // a commitment repeated in the batch is caught once the first one is inserted
+        let commitment_bytes =
+            validate_commitment(deps.storage, msg.pool_id, &mixer.merkle_tree, commitment)?;
+        let inserted_index = mixer.merkle_tree.insert(
+            deps.api,
+            msg.pool_id,
+            commitment_bytes,
+            &env.block,
+            deps.storage,
+        )?;
+        inserted_indices.push(inserted_index.to_string());
+        epochs.push(mixer.merkle_tree.epoch.to_string());
+    }
+    // the tree is persisted once for the whole batch
+    mixer_write(deps.storage, msg.pool_id, &mixer)?;
+
+    let commitments: Vec<String> = msg.commitments.iter().map(Binary::to_base64).collect();
+    Ok(
+        Response::new().add_event(Event::new("mixer-deposit").add_attributes(vec![
+            attr("action", "batch_deposit"),
+            attr("pool_id", msg.pool_id.to_string()),
+            attr("epochs", epochs.join(",")),
+            attr("inserted_indices", inserted_indices.join(",")),
+            attr("commitments", commitments.join(",")),
+        ])),
+    )
 }
 
 pub fn withdraw(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     msg: WithdrawMsg,
 ) -> Result<Response, ContractError> {
-    let recipient = msg.recipient;
-    let relayer = msg.relayer;
     let fee = msg.fee;
     let refund = msg.refund;
     let root_bytes = element_encoder(msg.root.as_slice());
     let nullifier_hash_bytes = element_encoder(msg.nullifier_hash.as_slice());
     let proof_bytes_vec = msg.proof_bytes.to_vec();
 
-    let mixer = mixer_read(deps.storage)?;
+    let status = pause_status_read(deps.storage)?;
+    if status.withdrawals_paused {
+        return Err(ContractError::WithdrawalsPaused {
+            reason: status.reason.unwrap_or_default(),
+        });
+    }
+
+    // Validate the addresses before spending gas on the proof
+    let recipient =
+        deps.api
+            .addr_validate(&msg.recipient)
+            .map_err(|_| ContractError::InvalidRecipient {
+                address: msg.recipient.clone(),
+            })?;
+    let relayer =
+        match msg.relayer.as_deref() {
+            None | Some("") => None,
+            Some(relayer) => Some(deps.api.addr_validate(relayer).map_err(|_| {
+                ContractError::InvalidRelayer {
+                    address: relayer.to_string(),
+                }
+            })?),
+        };
+    if !fee.is_zero() && relayer.is_none() {
+        return Err(ContractError::RelayerRequired {});
+    }
+
+    if let Some(valid_until_height) = msg.valid_until_height {
+        if env.block.height > valid_until_height {
+            return Err(ContractError::ProofExpired { valid_until_height });
+        }
+    }
+
+    let config = config_read(deps.storage)?;
+    let mixer = load_mixer(deps.storage, msg.pool_id)?;
 
     // Validations
-    let sent_funds = info.funds;
-    if !refund.is_zero() && (sent_funds.len() != 1 || sent_funds[0].amount != refund) {
-        return Err(ContractError::Std(StdError::GenericErr {
-            msg: "Sent insufficent refund".to_string(),
-        }));
+    assert_refund(refund, &config.refund_denom, &info.funds)?;
+    if let Some(relayer) = relayer.as_ref() {
+        assert_relayer_fee(deps.storage, relayer, fee, mixer.deposit_size)?;
     }
+    // The recipient gets the deposit net of the relayer and protocol fees
+    let protocol_fee = config
+        .protocol_fee
+        .as_ref()
+        .map_or_else(Uint128::zero, |protocol_fee| {
+            protocol_fee.amount(mixer.deposit_size)
+        });
+    let amt_to_recipient = mixer
+        .deposit_size
+        .checked_sub(fee + protocol_fee)
+        .map_err(|_| ContractError::FeeExceedsDepositSize {
+            fee: fee + protocol_fee,
+            deposit_size: mixer.deposit_size,
+        })?;
 
-    let merkle_tree = mixer.merkle_tree;
-    if !merkle_tree.is_known_root(root_bytes, deps.storage) {
-        return Err(ContractError::Std(StdError::GenericErr {
-            msg: "Root is not known".to_string(),
-        }));
+    // Roots of past epochs stay withdrawable
+    let merkle_tree = match msg.epoch {
+        Some(epoch) if epoch != mixer.merkle_tree.epoch => {
+            epoch_tree_read(deps.storage, msg.pool_id, epoch)
+                .map_err(|_| ContractError::UnknownEpoch { epoch })?
+        }
+        _ => mixer.merkle_tree,
+    };
+    if !merkle_tree.is_known_root(msg.pool_id, root_bytes, deps.storage) {
+        return Err(ContractError::UnknownRoot);
     }
 
-    if nullifier_read(deps.storage, &nullifier_hash_bytes) {
-        return Err(ContractError::Std(StdError::GenericErr {
-            msg: "Nullifier is known".to_string(),
-        }));
+    if nullifier_read(deps.storage, msg.pool_id, &nullifier_hash_bytes) {
+        return Err(ContractError::AlreadyRevealedNullfier);
     }
 
     // Format the public input bytes
-    let recipient_bytes = truncate_and_pad(recipient.as_bytes());
-    let relayer_bytes = truncate_and_pad(relayer.as_bytes());
+    let recipient_bytes = encode_address(recipient.as_str());
+    let relayer_bytes = encode_address(relayer.as_ref().map_or("", Addr::as_str));
+    // Bind the proof to this deployment and its expiry, provers append them to the relayer bytes
+    let domain_bytes = encode_domain(&env.block.chain_id, env.contract.address.as_str());
+    let expiry_bytes = encode_expiry(msg.valid_until_height);
 
-    // limit arbitrary data bytes to 96 bytes
     let mut arbitrary_data_bytes = Vec::new();
     arbitrary_data_bytes.extend_from_slice(&recipient_bytes);
     arbitrary_data_bytes.extend_from_slice(&relayer_bytes);
+    arbitrary_data_bytes.extend_from_slice(&domain_bytes);
+    arbitrary_data_bytes.extend_from_slice(&expiry_bytes);
     arbitrary_data_bytes.extend_from_slice(&fee.to_le_bytes());
     arbitrary_data_bytes.extend_from_slice(&refund.to_le_bytes());
 
@@ -168,101 +934,535 @@ pub fn withdraw(
     // Verify the proof
     let result = deps
         .api
-        .groth16_verify(&bytes, &proof_bytes_vec, &mixer.vk_raw, merkle_tree.curve)
+        .groth16_verify(&bytes, &proof_bytes_vec, &config.vk_raw, merkle_tree.curve)
         .map_err(|_| ContractError::VerifyError)?;
 
     if !result {
-        return Err(ContractError::Std(StdError::GenericErr {
-            msg: "Invalid withdraw proof".to_string(),
-        }));
+        return Err(ContractError::InvalidWithdrawProof);
     }
 
     // Set used nullifier to true after successful verification
     nullifier_write(
         deps.storage,
+        msg.pool_id,
         &element_encoder(msg.nullifier_hash.as_slice()),
     );
 
+    if let Some(relayer) = relayer.as_ref() {
+        let mut stats = relayer_stats_read(deps.storage, relayer)?;
+        stats.record(msg.pool_id, fee);
+        relayer_stats_write(deps.storage, relayer, &stats)?;
+    }
+
     // Send the funds
     let mut msgs: Vec<CosmosMsg> = vec![];
 
     // Send the funds to "recipient"
-    let amt_to_recipient = match mixer.deposit_size.checked_sub(fee) {
-        Ok(v) => v,
-        Err(e) => {
-            return Err(ContractError::Std(StdError::GenericErr {
-                msg: e.to_string(),
-            }))
-        }
-    };
-
     if !amt_to_recipient.is_zero() {
-        msgs.push(CosmosMsg::Bank(BankMsg::Send {
-            to_address: recipient.clone(),
-            amount: vec![Coin {
-                denom: mixer.native_token_denom.clone(),
-                amount: amt_to_recipient,
-            }],
-        }));
+        msgs.push(
+            mixer
+                .asset
+                .transfer_msg(recipient.to_string(), amt_to_recipient)?,
+        );
     }
-    if !fee.is_zero() {
-        msgs.push(CosmosMsg::Bank(BankMsg::Send {
-            to_address: relayer,
-            amount: vec![Coin {
-                denom: mixer.native_token_denom,
-                amount: fee,
-            }],
-        }));
+    if let Some(relayer) = relayer.filter(|_| !fee.is_zero()) {
+        msgs.push(mixer.asset.transfer_msg(relayer.to_string(), fee)?);
+    }
+    let mut protocol_fee_attrs = vec![attr("protocol_fee", protocol_fee)];
+    if let Some(treasury) = config
+        .protocol_fee
+        .map(|protocol_fee| protocol_fee.treasury)
+    {
+        if !protocol_fee.is_zero() {
+            msgs.push(
+                mixer
+                    .asset
+                    .transfer_msg(treasury.to_string(), protocol_fee)?,
+            );
+        }
+        protocol_fee_attrs.push(attr("treasury", treasury));
     }
 
+    let refund_coin = Coin::new(refund.u128(), config.refund_denom);
     if !refund.is_zero() {
         msgs.push(CosmosMsg::Bank(BankMsg::Send {
-            to_address: recipient.clone(),
-            amount: sent_funds,
+            to_address: recipient.to_string(),
+            amount: vec![refund_coin.clone()],
         }));
     }
 
+    Ok(Response::new().add_messages(msgs).add_event(
+        Event::new("mixer-withdraw")
+            .add_attributes(vec![
+                attr("action", "withdraw"),
+                attr("pool_id", msg.pool_id.to_string()),
+                attr("epoch", merkle_tree.epoch.to_string()),
+                attr("recipient", recipient.to_string()),
+                attr("root", msg.root.to_base64()),
+                attr("nullifier_hash", msg.nullifier_hash.to_base64()),
+                attr("refund", refund_coin.to_string()),
+            ])
+            .add_attributes(protocol_fee_attrs),
+    ))
+}
+
+/// The relayer attaches exactly the refund, in the refund denom
+fn assert_refund(refund: Uint128, refund_denom: &str, funds: &[Coin]) -> Result<(), ContractError> {
+    if refund.is_zero() {
+        if !funds.is_empty() {
+            return Err(ContractError::UnexpectedRefund {});
+        }
+        return Ok(());
+    }
+    if funds.is_empty() {
+        return Err(ContractError::MissingRefund { refund });
+    }
+    if let Some(coin) = funds.iter().find(|coin| coin.denom != refund_denom) {
+        return Err(ContractError::RefundDenomMismatch {
+            expected: refund_denom.to_string(),
+            denom: coin.denom.clone(),
+        });
+    }
+    // the bank module merges coins of the same denom
+    if funds[0].amount != refund {
+        return Err(ContractError::RefundAmountMismatch {
+            expected: refund,
+            received: funds[0].amount,
+        });
+    }
+    Ok(())
+}
+
+/// Major, minor and patch of a semver version, pre-release and build metadata are ignored
+fn parse_version(version: &str) -> Result<(u64, u64, u64), ContractError> {
+    let invalid = || ContractError::InvalidContractVersion {
+        version: version.to_string(),
+    };
+    let core = version.split(['-', '+']).next().unwrap_or_default();
+    let parts = core
+        .split('.')
+        .map(|part| part.parse::<u64>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [major, minor, patch] => Ok((major, minor, patch)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    // 0.1.0 stored no contract version
+    let from_version = match get_contract_version(deps.storage)? {
+        Some(stored) => {
+            if stored.contract != CONTRACT_NAME {
+                return Err(ContractError::CannotMigrate {
+                    previous_contract: stored.contract,
+                });
+            }
+            if parse_version(&stored.version)? > parse_version(CONTRACT_VERSION)? {
+                return Err(ContractError::CannotDowngrade {
+                    stored_version: stored.version,
+                    version: CONTRACT_VERSION.to_string(),
+                });
+            }
+            stored.version
+        }
+        None => {
+            let admin = msg.admin.ok_or(ContractError::MigrationAdminRequired {})?;
+            let admin = validate_addr(deps.api, &admin)?;
+            migrate_from_v0_1(deps.storage, admin)?;
+            "0.1.0".to_string()
+        }
+    };
+
+    let mut config = config_read(deps.storage)?;
+    if let Some(vk_update_delay) = msg.vk_update_delay {
+        assert_vk_update_delay(vk_update_delay)?;
+        config.vk_update_delay = vk_update_delay;
+    }
+    if let Some(excess_funds) = msg.excess_funds {
+        config.excess_funds = excess_funds;
+    }
+    if let Some(refund_denom) = msg.refund_denom {
+        config.refund_denom = refund_denom;
+    }
+    config_write(deps.storage, &config)?;
+
+    if let Some(size) = msg.root_history_size {
+        assert_root_history_size(size)?;
+        for (pool_id, mut mixer) in mixers_read(deps.storage)? {
+            mixer
+                .merkle_tree
+                .resize_root_history(pool_id, size, deps.storage)?;
+            mixer_write(deps.storage, pool_id, &mixer)?;
+        }
+    }
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
     Ok(Response::new()
-        .add_messages(msgs)
-        .add_event(Event::new("mixer-withdraw").add_attributes(vec![
-            attr("action", "withdraw"),
-            attr("recipient", recipient),
-            attr("root", msg.root.to_base64()),
-            attr("nullifier_hash", msg.nullifier_hash.to_base64()),
-        ])))
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", from_version)
+        .add_attribute("to_version", CONTRACT_VERSION))
 }
 
-#[entry_point]
+#[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::Config {} => to_binary(&get_config(deps)?),
-        QueryMsg::MerkleTreeInfo {} => to_binary(&get_merkle_tree_info(deps)?),
-        QueryMsg::MerkleRoot { id } => to_binary(&get_merkle_root(deps, id)?),
+        QueryMsg::Config { pool_id } => to_binary(&get_config(deps, pool_id)?),
+        QueryMsg::MerkleTreeInfo { pool_id } => to_binary(&get_merkle_tree_info(deps, pool_id)?),
+        QueryMsg::MerkleRoot { pool_id, id, epoch } => {
+            to_binary(&get_merkle_root(deps, pool_id, id, epoch)?)
+        }
+        QueryMsg::RootHistory {
+            pool_id,
+            start_after,
+            limit,
+            epoch,
+        } => to_binary(&get_root_history(deps, pool_id, start_after, limit, epoch)?),
+        QueryMsg::Pools {} => to_binary(&get_pools(deps)?),
+        QueryMsg::Status {} => to_binary(&get_status(deps)?),
+        QueryMsg::VerifyingKey {} => to_binary(&get_verifying_key(deps)?),
+        QueryMsg::IsSpent {
+            pool_id,
+            nullifier_hash,
+        } => to_binary(&is_spent(deps, pool_id, nullifier_hash)?),
+        QueryMsg::AreSpent {
+            pool_id,
+            nullifier_hashes,
+        } => to_binary(&are_spent(deps, pool_id, nullifier_hashes)?),
+        QueryMsg::IsKnownRoot {
+            pool_id,
+            root,
+            epoch,
+        } => to_binary(&is_known_root(deps, pool_id, root, epoch)?),
+        QueryMsg::Commitments {
+            pool_id,
+            start_after,
+            limit,
+            epoch,
+        } => to_binary(&get_commitments(deps, pool_id, start_after, limit, epoch)?),
+        QueryMsg::CommitmentIndex {
+            pool_id,
+            commitment,
+            epoch,
+        } => to_binary(&get_commitment_index(deps, pool_id, commitment, epoch)?),
+        QueryMsg::MerklePath {
+            pool_id,
+            index,
+            epoch,
+        } => to_binary(&get_merkle_path(deps, pool_id, index, epoch)?),
+        QueryMsg::RelayerPolicy {} => to_binary(&get_relayer_policy(deps)?),
+        QueryMsg::Relayer { address } => to_binary(&get_relayer(deps, address)?),
+        QueryMsg::Relayers { start_after, limit } => {
+            to_binary(&get_relayers(deps, start_after, limit)?)
+        }
     }
 }
 
-fn get_config(deps: Deps) -> StdResult<ConfigResponse> {
-    let mixer = mixer_read(deps.storage)?;
-    let native_token_denom = mixer.native_token_denom;
+fn get_config(deps: Deps, pool_id: u32) -> StdResult<ConfigResponse> {
+    let config = config_read(deps.storage)?;
+    let mixer = mixer_read(deps.storage, pool_id)?;
+    let asset = mixer.asset;
 
     let deposit_size = mixer.deposit_size.to_string();
     Ok(ConfigResponse {
-        native_token_denom,
+        asset,
         deposit_size,
+        curve: Curve::try_from(mixer.merkle_tree.curve)?,
+        excess_funds: config.excess_funds,
+        refund_denom: config.refund_denom,
+        root_history_size: mixer.merkle_tree.root_history_size,
+        zero_leaf: Binary::from(read_zero(deps.storage, pool_id, 0).as_slice()),
+        protocol_fee: config.protocol_fee.map(|protocol_fee| ProtocolFeeResponse {
+            rate: protocol_fee.rate,
+            treasury: protocol_fee.treasury.to_string(),
+            amount: protocol_fee.amount(mixer.deposit_size),
+        }),
     })
 }
 
-fn get_merkle_tree_info(deps: Deps) -> StdResult<MerkleTreeInfoResponse> {
-    let mixer = mixer_read(deps.storage)?;
+/// Tree of the given epoch, the current one when absent
+fn load_tree(deps: Deps, pool_id: u32, epoch: Option<u32>) -> StdResult<MerkleTree> {
+    let tree = mixer_read(deps.storage, pool_id)?.merkle_tree;
+    match epoch {
+        Some(epoch) if epoch != tree.epoch => epoch_tree_read(deps.storage, pool_id, epoch),
+        _ => Ok(tree),
+    }
+}
+
+fn get_merkle_tree_info(deps: Deps, pool_id: u32) -> StdResult<MerkleTreeInfoResponse> {
+    let mixer = mixer_read(deps.storage, pool_id)?;
     Ok(MerkleTreeInfoResponse {
         levels: mixer.merkle_tree.levels,
         current_root_index: mixer.merkle_tree.current_root_index,
         next_index: mixer.merkle_tree.next_index,
+        epoch: mixer.merkle_tree.epoch,
     })
 }
 
-fn get_merkle_root(deps: Deps, id: u32) -> StdResult<MerkleRootResponse> {
-    let root = read_root(deps.storage, id);
+fn get_merkle_root(
+    deps: Deps,
+    pool_id: u32,
+    id: u32,
+    epoch: Option<u32>,
+) -> StdResult<MerkleRootResponse> {
+    let tree = load_tree(deps, pool_id, epoch)?;
+    let root = read_root(deps.storage, pool_id, tree.epoch, id);
     let root_binary = Binary::from(root.as_slice());
-    Ok(MerkleRootResponse { root: root_binary })
+    Ok(MerkleRootResponse {
+        root: root_binary,
+        epoch: tree.epoch,
+    })
+}
+
+fn get_root_history(
+    deps: Deps,
+    pool_id: u32,
+    start_after: Option<u32>,
+    limit: Option<u32>,
+    epoch: Option<u32>,
+) -> StdResult<RootHistoryResponse> {
+    let tree = load_tree(deps, pool_id, epoch)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let size = tree.root_history_size;
+
+    // walk the ring backwards from the current root, `age` is the number of inserts since
+    let skip = match start_after {
+        Some(index) if index >= size => {
+            return Err(StdError::generic_err("Root index out of range"))
+        }
+        Some(index) => (tree.current_root_index + size - index) % size + 1,
+        None => 0,
+    };
+
+    let mut roots = vec![];
+    for age in skip..size {
+        // the ring is not filled yet
+        if age > tree.next_index || roots.len() == limit {
+            break;
+        }
+        let index = (tree.current_root_index + size - age) % size;
+        let root = read_root(deps.storage, pool_id, tree.epoch, index);
+        // slots emptied by a resize
+        if root == DEFAULT_LEAF {
+            break;
+        }
+        let info = read_root_info(deps.storage, pool_id, tree.epoch, index)?;
+        roots.push(RootHistoryEntry {
+            index,
+            root: Binary::from(root.as_slice()),
+            leaf_count: info
+                .as_ref()
+                .map_or(tree.next_index - age, |info| info.leaf_count),
+            height: info.as_ref().map(|info| info.height),
+            time: info.map(|info| info.time),
+        });
+    }
+    Ok(RootHistoryResponse {
+        epoch: tree.epoch,
+        roots,
+    })
+}
+
+fn get_pools(deps: Deps) -> StdResult<PoolsResponse> {
+    let pools = mixers_read(deps.storage)?
+        .into_iter()
+        .map(|(pool_id, mixer)| PoolResponse {
+            pool_id,
+            asset: mixer.asset,
+            deposit_size: mixer.deposit_size.to_string(),
+            next_index: mixer.merkle_tree.next_index,
+        })
+        .collect();
+    Ok(PoolsResponse { pools })
+}
+
+fn get_status(deps: Deps) -> StdResult<StatusResponse> {
+    let config = config_read(deps.storage)?;
+    let status = pause_status_read(deps.storage)?;
+    Ok(StatusResponse {
+        admin: config.admin.to_string(),
+        pending_admin: config.pending_admin.map(|addr| addr.to_string()),
+        deposits_paused: status.deposits_paused,
+        withdrawals_paused: status.withdrawals_paused,
+        reason: status.reason,
+    })
+}
+
+fn get_relayer_policy(deps: Deps) -> StdResult<RelayerPolicyResponse> {
+    let policy = relayer_policy_read(deps.storage)?;
+    Ok(RelayerPolicyResponse {
+        registration_required: policy.registration_required,
+        max_fee_bps: policy.max_fee_bps,
+    })
+}
+
+fn relayer_response(
+    deps: Deps,
+    address: Addr,
+    relayer: Option<Relayer>,
+) -> StdResult<RelayerResponse> {
+    let stats = relayer_stats_read(deps.storage, &address)?;
+    Ok(RelayerResponse {
+        address: address.to_string(),
+        fee_bps: relayer.as_ref().map(|relayer| relayer.fee_bps),
+        metadata_url: relayer.map(|relayer| relayer.metadata_url),
+        withdrawals: stats.withdrawals,
+        fees_earned: stats.fees_earned,
+    })
+}
+
+fn get_relayer(deps: Deps, address: String) -> StdResult<RelayerResponse> {
+    let address = deps.api.addr_validate(&address).map_err(|_| {
+        StdError::generic_err(ContractError::InvalidAddress { address }.to_string())
+    })?;
+    let relayer = relayer_read(deps.storage, &address)?;
+    relayer_response(deps, address, relayer)
+}
+
+fn get_relayers(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<RelayersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(Addr::unchecked);
+    let relayers = relayers_read(deps.storage, start_after.as_ref(), limit)?
+        .into_iter()
+        .map(|(address, relayer)| relayer_response(deps, address, Some(relayer)))
+        .collect::<StdResult<_>>()?;
+    Ok(RelayersResponse { relayers })
+}
+
+fn get_verifying_key(deps: Deps) -> StdResult<VerifyingKeyResponse> {
+    let config = config_read(deps.storage)?;
+    Ok(VerifyingKeyResponse {
+        vk_hash: Binary::from(sha256(&config.vk_raw)),
+        pending_vk_hash: config
+            .pending_vk
+            .as_ref()
+            .map(|pending_vk| Binary::from(sha256(&pending_vk.vk_raw))),
+        activates_at: config.pending_vk.map(|pending_vk| pending_vk.activates_at),
+        vk_update_delay: config.vk_update_delay,
+    })
+}
+
+fn is_spent(deps: Deps, pool_id: u32, nullifier_hash: Binary) -> StdResult<IsSpentResponse> {
+    let spent = nullifier_read(
+        deps.storage,
+        pool_id,
+        &element_encoder(nullifier_hash.as_slice()),
+    );
+    Ok(IsSpentResponse { spent })
+}
+
+fn are_spent(
+    deps: Deps,
+    pool_id: u32,
+    nullifier_hashes: Vec<Binary>,
+) -> StdResult<AreSpentResponse> {
+    let spent = nullifier_hashes
+        .iter()
+        .map(|nullifier_hash| {
+            nullifier_read(
+                deps.storage,
+                pool_id,
+                &element_encoder(nullifier_hash.as_slice()),
+            )
+        })
+        .collect();
+    Ok(AreSpentResponse { spent })
+}
+
+fn is_known_root(
+    deps: Deps,
+    pool_id: u32,
+    root: Binary,
+    epoch: Option<u32>,
+) -> StdResult<IsKnownRootResponse> {
+    let tree = load_tree(deps, pool_id, epoch)?;
+    let known = tree.is_known_root(pool_id, element_encoder(root.as_slice()), deps.storage);
+    Ok(IsKnownRootResponse {
+        known,
+        epoch: tree.epoch,
+    })
+}
+
+fn get_commitments(
+    deps: Deps,
+    pool_id: u32,
+    start_after: Option<u32>,
+    limit: Option<u32>,
+    epoch: Option<u32>,
+) -> StdResult<CommitmentsResponse> {
+    let tree = load_tree(deps, pool_id, epoch)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let commitments = read_commitments(deps.storage, pool_id, tree.epoch, start_after, limit)
+        .into_iter()
+        .map(|(index, commitment)| CommitmentResponse {
+            index,
+            commitment: Binary::from(commitment.as_slice()),
+        })
+        .collect();
+    Ok(CommitmentsResponse {
+        epoch: tree.epoch,
+        commitments,
+    })
+}
+
+fn get_commitment_index(
+    deps: Deps,
+    pool_id: u32,
+    commitment: Binary,
+    epoch: Option<u32>,
+) -> StdResult<CommitmentIndexResponse> {
+    let tree = load_tree(deps, pool_id, epoch)?;
+    let index = read_commitment_index(
+        deps.storage,
+        pool_id,
+        tree.epoch,
+        &element_encoder(commitment.as_slice()),
+    );
+    Ok(CommitmentIndexResponse {
+        index,
+        epoch: tree.epoch,
+    })
+}
+
+fn get_merkle_path(
+    deps: Deps,
+    pool_id: u32,
+    index: u32,
+    epoch: Option<u32>,
+) -> StdResult<MerklePathResponse> {
+    let tree = load_tree(deps, pool_id, epoch)?;
+    if index >= tree.next_index {
+        return Err(StdError::generic_err("Leaf index is not inserted"));
+    }
+
+    let mut path_elements = Vec::with_capacity(tree.levels as usize);
+    let mut current_index = index;
+    for level in 0..tree.levels {
+        let sibling = current_index ^ 1;
+        // number of non empty nodes on this level
+        let filled = ((tree.next_index - 1) >> level) + 1;
+        let node = if sibling < filled {
+            // leaves inserted before 0.2.0 did not keep their nodes
+            read_node(deps.storage, pool_id, tree.epoch, level, sibling)
+                .ok_or_else(|| StdError::not_found("merkle tree node"))?
+        } else {
+            read_zero(deps.storage, pool_id, level)
+        };
+        path_elements.push(Binary::from(node.as_slice()));
+        current_index /= 2;
+    }
+
+    let root = read_root(deps.storage, pool_id, tree.epoch, tree.current_root_index);
+    Ok(MerklePathResponse {
+        index,
+        path_elements,
+        root: Binary::from(root.as_slice()),
+        epoch: tree.epoch,
+    })
 }
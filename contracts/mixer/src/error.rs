@@ -1,56 +1,265 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{StdError, Uint128};
 use thiserror::Error;
 
+/// Every error string starts with its `code` in brackets, e.g. `[3008] Root is not known`.
+/// Codes are stable: 1xxx are admin and setup errors, 2xxx deposit errors,
+/// 3xxx withdraw errors and 4xxx merkle tree errors.
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
-    #[error("{0}")]
+    #[error("[{}] {}", self.code(), .0)]
     Std(#[from] StdError),
 
-    #[error("Unauthorized")]
+    #[error("[{}] Unauthorized", self.code())]
     Unauthorized {},
 
-    #[error("Unnecessary_funds")]
+    #[error("[{}] Unnecessary_funds", self.code())]
     UnnecessaryFunds {},
 
-    #[error("Insufficient_funds")]
-    InsufficientFunds {},
+    #[error("[{}] Cannot migrate from {previous_contract}", self.code())]
+    CannotMigrate { previous_contract: String },
+
+    #[error("[{}] Admin is required to migrate from 0.1.0", self.code())]
+    MigrationAdminRequired {},
+
+    #[error("[{}] Merkle tree levels must be between 1 and {max}, got {levels}", self.code())]
+    InvalidMerkleTreeLevels { levels: u32, max: u32 },
+
+    #[error("[{}] Zero leaf must be a canonical 32 bytes field element", self.code())]
+    InvalidZeroLeaf {},
+
+    #[error("[{}] Root history size must be between 1 and {max}, got {size}", self.code())]
+    InvalidRootHistorySize { size: u32, max: u32 },
+
+    #[error("[{}] Verifying key update delay must be at most {max} seconds, got {delay}", self.code())]
+    InvalidVkUpdateDelay { delay: u64, max: u64 },
+
+    #[error("[{}] Invalid address: {address}", self.code())]
+    InvalidAddress { address: String },
+
+    #[error("[{}] At least one pool is required", self.code())]
+    NoPools {},
+
+    #[error("[{}] No pending verifying key", self.code())]
+    NoPendingVerifyingKey {},
+
+    #[error("[{}] Verifying key is timelocked until {activates_at}", self.code())]
+    VerifyingKeyTimelocked { activates_at: u64 },
+
+    #[error("[{}] Fee of {fee_bps} bps exceeds the maximum of {max} bps", self.code())]
+    FeeBpsTooHigh { fee_bps: u16, max: u16 },
+
+    #[error("[{}] Relayer is not registered", self.code())]
+    RelayerNotRegistered {},
+
+    #[error("[{}] Metadata url must be at most {max} bytes", self.code())]
+    InvalidMetadataUrl { max: usize },
+
+    #[error("[{}] Protocol fee {fee} exceeds the maximum of {max} in pool {pool_id}", self.code())]
+    ProtocolFeeTooHigh {
+        pool_id: u32,
+        fee: Uint128,
+        max: Uint128,
+    },
+
+    #[error("[{}] Cannot migrate from {stored_version} down to {version}", self.code())]
+    CannotDowngrade {
+        stored_version: String,
+        version: String,
+    },
+
+    #[error("[{}] Invalid contract version {version}", self.code())]
+    InvalidContractVersion { version: String },
+
+    #[error("[{}] Unknown pool {pool_id}", self.code())]
+    UnknownPool { pool_id: u32 },
+
+    #[error("[{}] Unknown curve id {id}", self.code())]
+    InvalidCurve { id: u8 },
 
-    /* -------   mixer related error  ------- */
     /// Returned if the mixer is not initialized
-    #[error("NotInitialized")]
+    #[error("[{}] NotInitialized", self.code())]
     NotInitialized,
     /// Returned if the mixer is already initialized
-    #[error("AlreadyInitialized")]
+    #[error("[{}] AlreadyInitialized", self.code())]
     AlreadyInitialized,
+    // Failing to decode a hex string
+    #[error("[{}] DecodeError", self.code())]
+    DecodeError,
+
+    /* -------   deposit errors  ------- */
+    #[error("[{}] Deposits are paused: {reason}", self.code())]
+    DepositsPaused { reason: String },
+
+    #[error("[{}] Insufficient_funds", self.code())]
+    InsufficientFunds {},
+
+    #[error("[{}] Invalid_asset", self.code())]
+    InvalidAsset {},
+
+    #[error("[{}] Invalid deposit amount: expected {expected}, received {received}", self.code())]
+    InvalidDepositAmount {
+        expected: Uint128,
+        received: Uint128,
+    },
+
+    #[error("[{}] Unexpected funds in {denom}", self.code())]
+    UnexpectedDenom { denom: String },
+
+    #[error("[{}] Commitment must be 32 bytes, got {length}", self.code())]
+    InvalidCommitmentLength { length: usize },
+
+    #[error("[{}] Commitment is not a canonical field element", self.code())]
+    NonCanonicalCommitment {},
+
+    #[error("[{}] Commitment is already deposited", self.code())]
+    DuplicateCommitment {},
+
+    #[error("[{}] Invalid cw20 hook msg", self.code())]
+    InvalidHookMsg {},
+
+    #[error("[{}] No commitments to deposit", self.code())]
+    EmptyBatch {},
+
+    #[error("[{}] Batch of {count} deposits overflows the deposit amount", self.code())]
+    BatchTooLarge { count: usize },
+
+    /* -------   withdraw errors  ------- */
+    #[error("[{}] Withdrawals are paused: {reason}", self.code())]
+    WithdrawalsPaused { reason: String },
+
+    #[error("[{}] Invalid recipient address: {address}", self.code())]
+    InvalidRecipient { address: String },
+
+    #[error("[{}] Invalid relayer address: {address}", self.code())]
+    InvalidRelayer { address: String },
+
+    #[error("[{}] A relayer is required to pay a fee", self.code())]
+    RelayerRequired {},
+
+    #[error("[{}] Withdraw proof expired at height {valid_until_height}", self.code())]
+    ProofExpired { valid_until_height: u64 },
+
+    #[error("[{}] Sent refund of {received} does not match the refund of {expected}", self.code())]
+    RefundAmountMismatch {
+        expected: Uint128,
+        received: Uint128,
+    },
+
+    #[error("[{}] Refund must be sent in {expected}, got {denom}", self.code())]
+    RefundDenomMismatch { expected: String, denom: String },
+
+    #[error("[{}] A refund of {refund} is set but no funds were sent", self.code())]
+    MissingRefund { refund: Uint128 },
+
+    #[error("[{}] Funds were sent but the refund is zero", self.code())]
+    UnexpectedRefund {},
+
+    #[error("[{}] Relayer {address} is not registered", self.code())]
+    UnregisteredRelayer { address: String },
+
+    #[error("[{}] Fee {fee} exceeds the relayer fee cap of {max}", self.code())]
+    RelayerFeeTooHigh { fee: Uint128, max: Uint128 },
+
+    #[error("[{}] Unknown tree epoch {epoch}", self.code())]
+    UnknownEpoch { epoch: u32 },
+
+    #[error("[{}] Root is not known", self.code())]
+    UnknownRoot,
+
+    #[error("[{}] Nullifier is known", self.code())]
+    AlreadyRevealedNullfier,
+
+    #[error("[{}] Invalid withdraw proof", self.code())]
+    InvalidWithdrawProof,
+
+    #[error("[{}] Fee {fee} exceeds the deposit size {deposit_size}", self.code())]
+    FeeExceedsDepositSize { fee: Uint128, deposit_size: Uint128 },
+
+    #[error("[{}] Invalid arbitrary data passed", self.code())]
+    InvalidArbitraryData,
+
+    #[error("[{}] Invalid merkle roots", self.code())]
+    InvaidMerkleRoots,
+
+    /* -------   merkle tree errors  ------- */
     /// Returned if the merkle tree is full.
-    #[error("FullMerkleTree")]
+    #[error("[{}] FullMerkleTree", self.code())]
     MerkleTreeIsFull,
     /// Hash error
-    #[error("HashError")]
+    #[error("[{}] HashError", self.code())]
     HashError,
     /// Verify error
-    #[error("VerifyError")]
+    #[error("[{}] VerifyError", self.code())]
     VerifyError,
-    // Failing to decode a hex string
-    #[error("DecodeError")]
-    DecodeError,
-
     // Returned if a mapping item is not found
-    #[error("Mapping item not found")]
+    #[error("[{}] Mapping item not found", self.code())]
     ItemNotFound,
+}
 
-    #[error("Invalid merkle roots")]
-    InvaidMerkleRoots,
-
-    #[error("Unknown root")]
-    UnknownRoot,
+impl ContractError {
+    /// Stable numeric code of the error, new variants get new codes
+    pub fn code(&self) -> u32 {
+        match self {
+            ContractError::Std(_) => 1000,
+            ContractError::Unauthorized {} => 1001,
+            ContractError::UnnecessaryFunds {} => 1002,
+            ContractError::CannotMigrate { .. } => 1003,
+            ContractError::MigrationAdminRequired {} => 1004,
+            ContractError::InvalidMerkleTreeLevels { .. } => 1005,
+            ContractError::InvalidZeroLeaf {} => 1006,
+            ContractError::InvalidRootHistorySize { .. } => 1007,
+            ContractError::NoPendingVerifyingKey {} => 1008,
+            ContractError::VerifyingKeyTimelocked { .. } => 1009,
+            ContractError::NotInitialized => 1010,
+            ContractError::AlreadyInitialized => 1011,
+            ContractError::DecodeError => 1012,
+            ContractError::FeeBpsTooHigh { .. } => 1013,
+            ContractError::RelayerNotRegistered {} => 1014,
+            ContractError::InvalidMetadataUrl { .. } => 1015,
+            ContractError::ProtocolFeeTooHigh { .. } => 1016,
+            ContractError::CannotDowngrade { .. } => 1017,
+            ContractError::InvalidContractVersion { .. } => 1018,
+            ContractError::UnknownPool { .. } => 1019,
+            ContractError::InvalidCurve { .. } => 1020,
+            ContractError::InvalidVkUpdateDelay { .. } => 1021,
+            ContractError::NoPools {} => 1022,
+            ContractError::InvalidAddress { .. } => 1023,
 
-    #[error("Invalid withdraw proof")]
-    InvalidWithdrawProof,
+            ContractError::DepositsPaused { .. } => 2001,
+            ContractError::InsufficientFunds {} => 2002,
+            ContractError::InvalidAsset {} => 2003,
+            ContractError::InvalidDepositAmount { .. } => 2004,
+            ContractError::UnexpectedDenom { .. } => 2005,
+            ContractError::InvalidCommitmentLength { .. } => 2006,
+            ContractError::NonCanonicalCommitment {} => 2007,
+            ContractError::DuplicateCommitment {} => 2008,
+            ContractError::EmptyBatch {} => 2009,
+            ContractError::BatchTooLarge { .. } => 2010,
+            ContractError::InvalidHookMsg {} => 2011,
 
-    #[error("Invalid arbitrary data passed")]
-    InvalidArbitraryData,
+            ContractError::WithdrawalsPaused { .. } => 3001,
+            ContractError::InvalidRecipient { .. } => 3002,
+            ContractError::InvalidRelayer { .. } => 3003,
+            ContractError::RelayerRequired {} => 3004,
+            ContractError::ProofExpired { .. } => 3005,
+            ContractError::RefundAmountMismatch { .. } => 3006,
+            ContractError::UnknownEpoch { .. } => 3007,
+            ContractError::UnknownRoot => 3008,
+            ContractError::AlreadyRevealedNullfier => 3009,
+            ContractError::InvalidWithdrawProof => 3010,
+            ContractError::FeeExceedsDepositSize { .. } => 3011,
+            ContractError::InvalidArbitraryData => 3012,
+            ContractError::InvaidMerkleRoots => 3013,
+            ContractError::RefundDenomMismatch { .. } => 3014,
+            ContractError::MissingRefund { .. } => 3015,
+            ContractError::UnexpectedRefund {} => 3016,
+            ContractError::UnregisteredRelayer { .. } => 3017,
+            ContractError::RelayerFeeTooHigh { .. } => 3018,
 
-    #[error("Invalid nullifier that is already used")]
-    AlreadyRevealedNullfier,
+            ContractError::MerkleTreeIsFull => 4001,
+            ContractError::HashError => 4002,
+            ContractError::VerifyError => 4003,
+            ContractError::ItemNotFound => 4004,
+        }
+    }
 }
@@ -1,5 +1,7 @@
+pub mod asset;
 pub mod contract;
 pub mod error;
+pub mod migration;
 pub mod msg;
 pub mod state;
 pub mod utils;
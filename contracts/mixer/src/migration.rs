@@ -0,0 +1,85 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Binary, Storage, Uint128};
+use cosmwasm_storage::{prefixed, prefixed_read, singleton, singleton_read};
+
+use crate::asset::AssetInfo;
+use crate::error::ContractError;
+use crate::msg::ExcessFunds;
+use crate::state::{
+    config_write, mixer_write, save_root, save_subtree, Config, MerkleTree, Mixer,
+    DEFAULT_REFUND_DENOM, DEFAULT_ROOT_HISTORY_SIZE, FILLED_SUBTREES_KEY, MERKLE_ROOTS_KEY,
+};
+use crate::utils::element_encoder;
+
+/// Pool id given to the single mixer of a legacy deployment
+pub const LEGACY_POOL_ID: u32 = 0;
+
+/// Verifying key timelock given to a legacy deployment, two days
+pub const DEFAULT_VK_UPDATE_DELAY: u64 = 2 * 24 * 60 * 60;
+
+// put the length bytes at the first for compatibility with legacy singleton store
+pub const LEGACY_MIXER_KEY: &[u8] = b"mixer";
+
+/// Mixer layout of 0.1.0, a single native pool holding the verifying key
+#[cw_serde]
+pub struct MixerV0_1 {
+    pub deposit_size: Uint128,
+    pub native_token_denom: String,
+    pub vk_raw: Binary,
+    /// Stored without `epoch` and `root_history_size`, they take their serde defaults
+    pub merkle_tree: MerkleTree,
+}
+
+/// Move the 0.1.0 single mixer and its unprefixed stores into pool `LEGACY_POOL_ID`.
+/// Spent nullifiers are unbounded so they stay under the legacy prefix, see `nullifier_read`.
+/// Leaves inserted before the migration were never stored, so they are not in `Commitments`.
+pub fn migrate_from_v0_1(storage: &mut dyn Storage, admin: Addr) -> Result<(), ContractError> {
+    let legacy: MixerV0_1 = singleton_read(storage, LEGACY_MIXER_KEY).load()?;
+
+    config_write(
+        storage,
+        &Config {
+            admin,
+            pending_admin: None,
+            vk_raw: legacy.vk_raw,
+            vk_update_delay: DEFAULT_VK_UPDATE_DELAY,
+            pending_vk: None,
+            excess_funds: ExcessFunds::default(),
+            refund_denom: DEFAULT_REFUND_DENOM.to_string(),
+            protocol_fee: None,
+        },
+    )?;
+
+    for k in 0..DEFAULT_ROOT_HISTORY_SIZE {
+        let key = k.to_le_bytes();
+        let root = prefixed_read(storage, MERKLE_ROOTS_KEY).get(&key);
+        if let Some(root) = root {
+            save_root(storage, LEGACY_POOL_ID, 0, k, &element_encoder(&root));
+            prefixed(storage, MERKLE_ROOTS_KEY).remove(&key);
+        }
+    }
+
+    for k in 0..legacy.merkle_tree.levels {
+        let key = k.to_le_bytes();
+        let subtree = prefixed_read(storage, FILLED_SUBTREES_KEY).get(&key);
+        if let Some(subtree) = subtree {
+            save_subtree(storage, LEGACY_POOL_ID, 0, k, &element_encoder(&subtree));
+            prefixed(storage, FILLED_SUBTREES_KEY).remove(&key);
+        }
+    }
+
+    mixer_write(
+        storage,
+        LEGACY_POOL_ID,
+        &Mixer {
+            deposit_size: legacy.deposit_size,
+            asset: AssetInfo::NativeToken {
+                denom: legacy.native_token_denom,
+            },
+            merkle_tree: legacy.merkle_tree,
+        },
+    )?;
+    singleton::<MixerV0_1>(storage, LEGACY_MIXER_KEY).remove();
+
+    Ok(())
+}
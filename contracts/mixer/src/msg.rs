@@ -1,52 +1,426 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Binary, Uint128};
+use cosmwasm_std::{Binary, StdError, Timestamp, Uint128};
+
+use crate::asset::{AssetInfo, AssetInfoMsg};
+use crate::state::PoolFee;
 
 #[cw_serde]
 pub struct InstantiateMsg {
-    pub deposit_size: Uint128,
+    /// Defaults to the sender
+    pub admin: Option<String>,
     pub merkletree_levels: u32,
-    pub native_token_denom: String,
-    pub curve: u8,
+    pub curve: Curve,
+    /// Value of an empty leaf, such as a keccak "nothing up my sleeve" value reduced into the
+    /// field, little endian. Defaults to zero, the prover must use the same value
+    pub zero_leaf: Option<Binary>,
     pub vk_raw: Binary,
+    /// Seconds a proposed verifying key waits before it can be applied, at most a year
+    pub vk_update_delay: u64,
+    /// Defaults to `Reject`
+    pub excess_funds: Option<ExcessFunds>,
+    /// Denom relayers pay refunds in, normally the gas token. Defaults to `orai`
+    pub refund_denom: Option<String>,
+    /// Number of recent roots a withdraw proof can be made against, defaults to 100
+    pub root_history_size: Option<u32>,
+    /// Pools are assigned ids in order, starting from 0
+    pub pools: Vec<PoolMsg>,
+}
+
+/// Curve of the pool hashes and proofs
+#[cw_serde]
+#[derive(Copy)]
+pub enum Curve {
+    Bls381,
+    Bn254,
+}
+
+impl Curve {
+    /// Id taken by the poseidon, curve hash and groth16 host functions
+    pub fn id(self) -> u8 {
+        match self {
+            Curve::Bls381 => 0,
+            Curve::Bn254 => 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for Curve {
+    type Error = StdError;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            0 => Ok(Curve::Bls381),
+            1 => Ok(Curve::Bn254),
+            _ => Err(StdError::generic_err(format!("Unknown curve id {}", id))),
+        }
+    }
+}
+
+#[cw_serde]
+pub struct PoolMsg {
+    pub deposit_size: Uint128,
+    pub asset: AssetInfoMsg,
+}
+
+#[cw_serde]
+pub struct MigrateMsg {
+    /// Required when migrating from 0.1.0, which stored no admin
+    pub admin: Option<String>,
+    pub vk_update_delay: Option<u64>,
+    pub excess_funds: Option<ExcessFunds>,
+    pub refund_denom: Option<String>,
+    /// Resize the root history of the current tree of every pool, keeping the newest roots
+    pub root_history_size: Option<u32>,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
     Deposit(DepositMsg),
+    /// Deposit several commitments at once, the funds must be exactly one deposit size each
+    BatchDeposit(BatchDepositMsg),
     Withdraw(WithdrawMsg),
+    /// Deposit cw20 tokens, the hook msg must be a `Cw20HookMsg`
+    Receive(Cw20ReceiveMsg),
+    /// Propose a new admin, the role is transferred once they accept it
+    ProposeAdmin {
+        admin: String,
+    },
+    AcceptAdmin {},
+    Pause {
+        target: PauseTarget,
+        reason: String,
+    },
+    Unpause {
+        target: PauseTarget,
+    },
+    /// Propose a new verifying key, it can be applied once `vk_update_delay` has passed
+    ProposeVerifyingKey {
+        vk_raw: Binary,
+    },
+    CancelVerifyingKey {},
+    /// Apply the pending verifying key after its timelock, callable by anyone
+    ApplyVerifyingKey {},
+    /// Register the sender as a relayer, or update its registration
+    RegisterRelayer {
+        /// Fee charged, in basis points of the deposit size
+        fee_bps: u16,
+        metadata_url: String,
+    },
+    UnregisterRelayer {},
+    /// Admin only, None stops charging the protocol fee
+    UpdateProtocolFee {
+        protocol_fee: Option<ProtocolFeeMsg>,
+    },
+    /// Admin only, lowering the max fee also caps registered relayers above it
+    UpdateRelayerPolicy {
+        registration_required: Option<bool>,
+        max_fee_bps: Option<u16>,
+    },
+}
+
+/// What a deposit does with funds beyond the deposit size and coins of other denoms
+#[cw_serde]
+#[derive(Copy, Default)]
+pub enum ExcessFunds {
+    /// Only the exact deposit amount is accepted
+    #[default]
+    Reject,
+    /// The excess is sent back to the depositor
+    Refund,
+}
+
+#[cw_serde]
+pub struct ProtocolFeeMsg {
+    pub rate: ProtocolFeeRate,
+    pub treasury: String,
+}
+
+/// Rates are capped at 5% of the deposit size of every pool
+#[cw_serde]
+#[derive(Copy)]
+pub enum ProtocolFeeRate {
+    /// Basis points of the deposit size
+    Bps(u16),
+    /// Same amount in every pool, in the pool asset
+    Flat(Uint128),
+}
+
+#[cw_serde]
+pub enum PauseTarget {
+    Deposits,
+    Withdrawals,
+    All,
+}
+
+#[cw_serde]
+pub enum Cw20HookMsg {
+    Deposit(DepositMsg),
+    BatchDeposit(BatchDepositMsg),
+}
+
+/// Cw20ReceiveMsg should be de/serialized under `Receive()` variant in a ExecuteMsg
+#[cw_serde]
+pub struct Cw20ReceiveMsg {
+    pub sender: String,
+    pub amount: Uint128,
+    pub msg: Binary,
+}
+
+/// The subset of the cw20 execute interface used to pay out token pools
+#[cw_serde]
+pub enum Cw20ExecuteMsg {
+    Transfer { recipient: String, amount: Uint128 },
 }
 
 #[cw_serde]
 pub struct DepositMsg {
+    pub pool_id: u32,
     pub commitment: Binary,
 }
 
+#[cw_serde]
+pub struct BatchDepositMsg {
+    pub pool_id: u32,
+    pub commitments: Vec<Binary>,
+}
+
 #[cw_serde]
 pub struct WithdrawMsg {
+    pub pool_id: u32,
     pub proof_bytes: Binary,
     pub root: Binary,
     pub nullifier_hash: Binary,
     pub recipient: String,
-    pub relayer: String,
+    /// May be empty or absent when `fee` is zero
+    pub relayer: Option<String>,
     pub fee: Uint128,
+    /// Gas token sent by the relayer to the recipient, it must be attached in the refund denom
     pub refund: Uint128,
+    /// Last block height the proof can be used at, bound into the proof
+    pub valid_until_height: Option<u64>,
+    /// Epoch of the tree the root belongs to, defaults to the current one
+    pub epoch: Option<u32>,
 }
 
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
     #[returns(ConfigResponse)]
-    Config {},
+    Config { pool_id: u32 },
     #[returns(MerkleTreeInfoResponse)]
-    MerkleTreeInfo {},
+    MerkleTreeInfo { pool_id: u32 },
+    /// Raw root history slot, `RootHistory` tells which slots are filled
     #[returns(MerkleRootResponse)]
-    MerkleRoot { id: u32 },
+    MerkleRoot {
+        pool_id: u32,
+        id: u32,
+        /// Defaults to the current epoch
+        epoch: Option<u32>,
+    },
+    /// Known roots from the newest to the oldest, paginated by ring index
+    #[returns(RootHistoryResponse)]
+    RootHistory {
+        pool_id: u32,
+        start_after: Option<u32>,
+        limit: Option<u32>,
+        /// Defaults to the current epoch
+        epoch: Option<u32>,
+    },
+    #[returns(PoolsResponse)]
+    Pools {},
+    #[returns(StatusResponse)]
+    Status {},
+    #[returns(VerifyingKeyResponse)]
+    VerifyingKey {},
+    #[returns(IsSpentResponse)]
+    IsSpent {
+        pool_id: u32,
+        nullifier_hash: Binary,
+    },
+    #[returns(AreSpentResponse)]
+    AreSpent {
+        pool_id: u32,
+        nullifier_hashes: Vec<Binary>,
+    },
+    /// Whether the root is still in the pool root history
+    #[returns(IsKnownRootResponse)]
+    IsKnownRoot {
+        pool_id: u32,
+        root: Binary,
+        /// Defaults to the current epoch
+        epoch: Option<u32>,
+    },
+    /// Leaves of the pool tree ordered by index
+    #[returns(CommitmentsResponse)]
+    Commitments {
+        pool_id: u32,
+        start_after: Option<u32>,
+        limit: Option<u32>,
+        /// Defaults to the current epoch
+        epoch: Option<u32>,
+    },
+    #[returns(CommitmentIndexResponse)]
+    CommitmentIndex {
+        pool_id: u32,
+        commitment: Binary,
+        /// Defaults to the current epoch
+        epoch: Option<u32>,
+    },
+    /// Sibling path of a leaf against the current root
+    #[returns(MerklePathResponse)]
+    MerklePath {
+        pool_id: u32,
+        index: u32,
+        /// Defaults to the current epoch
+        epoch: Option<u32>,
+    },
+    #[returns(RelayerPolicyResponse)]
+    RelayerPolicy {},
+    /// Registration and stats of an address, registered or not
+    #[returns(RelayerResponse)]
+    Relayer { address: String },
+    /// Registered relayers ordered by address
+    #[returns(RelayersResponse)]
+    Relayers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+#[cw_serde]
+pub struct IsSpentResponse {
+    pub spent: bool,
+}
+
+#[cw_serde]
+pub struct AreSpentResponse {
+    /// Same order as the queried nullifier hashes
+    pub spent: Vec<bool>,
+}
+
+#[cw_serde]
+pub struct IsKnownRootResponse {
+    pub known: bool,
+    pub epoch: u32,
+}
+
+#[cw_serde]
+pub struct CommitmentResponse {
+    pub index: u32,
+    pub commitment: Binary,
+}
+
+#[cw_serde]
+pub struct CommitmentsResponse {
+    pub epoch: u32,
+    pub commitments: Vec<CommitmentResponse>,
+}
+
+#[cw_serde]
+pub struct RootHistoryEntry {
+    /// Slot of the root in the history ring
+    pub index: u32,
+    pub root: Binary,
+    /// Number of leaves inserted when the root was computed
+    pub leaf_count: u32,
+    /// Creation block, unknown for roots migrated from 0.1.0
+    pub height: Option<u64>,
+    pub time: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub struct RootHistoryResponse {
+    pub epoch: u32,
+    pub roots: Vec<RootHistoryEntry>,
+}
+
+#[cw_serde]
+pub struct MerklePathResponse {
+    pub index: u32,
+    /// Siblings from the leaf level up to just below the root
+    pub path_elements: Vec<Binary>,
+    pub root: Binary,
+    pub epoch: u32,
+}
+
+#[cw_serde]
+pub struct CommitmentIndexResponse {
+    /// None when the commitment was never deposited in the pool
+    pub index: Option<u32>,
+    pub epoch: u32,
+}
+
+#[cw_serde]
+pub struct VerifyingKeyResponse {
+    /// Sha256 of the active verifying key
+    pub vk_hash: Binary,
+    pub pending_vk_hash: Option<Binary>,
+    pub activates_at: Option<u64>,
+    pub vk_update_delay: u64,
+}
+
+#[cw_serde]
+pub struct RelayerPolicyResponse {
+    pub registration_required: bool,
+    pub max_fee_bps: u16,
+}
+
+#[cw_serde]
+pub struct RelayerResponse {
+    pub address: String,
+    /// None when the address is not registered
+    pub fee_bps: Option<u16>,
+    pub metadata_url: Option<String>,
+    pub withdrawals: u64,
+    /// Fees earned in the asset of each pool
+    pub fees_earned: Vec<PoolFee>,
+}
+
+#[cw_serde]
+pub struct RelayersResponse {
+    pub relayers: Vec<RelayerResponse>,
+}
+
+#[cw_serde]
+pub struct StatusResponse {
+    pub admin: String,
+    pub pending_admin: Option<String>,
+    pub deposits_paused: bool,
+    pub withdrawals_paused: bool,
+    pub reason: Option<String>,
 }
 
 #[cw_serde]
 pub struct ConfigResponse {
-    pub native_token_denom: String,
+    pub asset: AssetInfo,
+    pub deposit_size: String,
+    pub curve: Curve,
+    pub excess_funds: ExcessFunds,
+    pub refund_denom: String,
+    pub root_history_size: u32,
+    pub zero_leaf: Binary,
+    pub protocol_fee: Option<ProtocolFeeResponse>,
+}
+
+#[cw_serde]
+pub struct ProtocolFeeResponse {
+    pub rate: ProtocolFeeRate,
+    pub treasury: String,
+    /// Fee taken from each withdrawal of the pool
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct PoolResponse {
+    pub pool_id: u32,
+    pub asset: AssetInfo,
     pub deposit_size: String,
+    pub next_index: u32,
+}
+
+#[cw_serde]
+pub struct PoolsResponse {
+    pub pools: Vec<PoolResponse>,
 }
 
 #[cw_serde]
@@ -54,9 +428,12 @@ pub struct MerkleTreeInfoResponse {
     pub levels: u32,
     pub current_root_index: u32,
     pub next_index: u32,
+    /// Number of the current tree, it rolls over once full
+    pub epoch: u32,
 }
 
 #[cw_serde]
 pub struct MerkleRootResponse {
     pub root: Binary,
+    pub epoch: u32,
 }
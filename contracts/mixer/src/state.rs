@@ -1,30 +1,185 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Api, Binary, StdResult, Storage, Uint128};
-use cosmwasm_storage::{prefixed, prefixed_read, singleton, singleton_read};
+use cosmwasm_std::{
+    from_slice, to_vec, Addr, Api, Binary, BlockInfo, Order, StdResult, Storage, Timestamp, Uint128,
+};
+use cosmwasm_storage::{
+    bucket, bucket_read, singleton, singleton_read, Bucket, PrefixedStorage, ReadonlyBucket,
+    ReadonlyPrefixedStorage,
+};
 
+use crate::asset::AssetInfo;
 use crate::error::ContractError;
+use crate::migration::LEGACY_POOL_ID;
+use crate::msg::{ExcessFunds, ProtocolFeeRate};
 use crate::utils::element_encoder;
 use crate::zeroes::{self, DEFAULT_LEAF};
 
-// History length of merkle tree root
-pub const ROOT_HISTORY_SIZE: u32 = 100;
+// History length of merkle tree root, the one of trees written before it was configurable
+pub const DEFAULT_ROOT_HISTORY_SIZE: u32 = 100;
+pub const MAX_ROOT_HISTORY_SIZE: u32 = 10_000;
+// a verifying key update can be held back for at most a year
+pub const MAX_VK_UPDATE_DELAY: u64 = 365 * 24 * 60 * 60;
+pub const DEFAULT_REFUND_DENOM: &str = "orai";
+// leaf indexes are u32, a full tree leaves `next_index` at 2^levels
+pub const MAX_MERKLE_TREE_LEVELS: u32 = 31;
+// relayer fees are capped in basis points of the deposit size
+pub const MAX_FEE_BPS: u16 = 10_000;
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 500;
 
-/// Mixer
+/// Config, shared by every pool of the mixer
+#[cw_serde]
+pub struct Config {
+    pub admin: Addr,
+    /// Proposed admin, who has to accept the role before it is transferred
+    pub pending_admin: Option<Addr>,
+    pub vk_raw: Binary,
+    /// Seconds a proposed verifying key waits before it can be applied
+    pub vk_update_delay: u64,
+    pub pending_vk: Option<PendingVerifyingKey>,
+    pub excess_funds: ExcessFunds,
+    /// Denom relayers pay refunds in
+    pub refund_denom: String,
+    /// Cut of every withdrawal sent to the treasury
+    pub protocol_fee: Option<ProtocolFee>,
+}
+
+/// ProtocolFee, taken from the deposit size of each withdrawal
+#[cw_serde]
+pub struct ProtocolFee {
+    pub rate: ProtocolFeeRate,
+    pub treasury: Addr,
+}
+
+impl ProtocolFee {
+    /// Fee taken from a withdrawal of the deposit size
+    pub fn amount(&self, deposit_size: Uint128) -> Uint128 {
+        match self.rate {
+            ProtocolFeeRate::Bps(bps) => deposit_size.multiply_ratio(bps, MAX_FEE_BPS),
+            ProtocolFeeRate::Flat(amount) => amount,
+        }
+    }
+}
+
+/// PendingVerifyingKey, a proposed key waiting for its timelock
+#[cw_serde]
+pub struct PendingVerifyingKey {
+    pub vk_raw: Binary,
+    pub activates_at: u64,
+}
+
+/// ContractVersion, the cw2 contract info of the deployed code
+#[cw_serde]
+pub struct ContractVersion {
+    pub contract: String,
+    pub version: String,
+}
+
+/// PauseStatus, the circuit breaker applied to every pool
+#[cw_serde]
+#[derive(Default)]
+pub struct PauseStatus {
+    pub deposits_paused: bool,
+    pub withdrawals_paused: bool,
+    pub reason: Option<String>,
+}
+
+/// RelayerPolicy, how withdraw treats relayers
+#[cw_serde]
+pub struct RelayerPolicy {
+    /// Only registered relayers can relay withdrawals
+    pub registration_required: bool,
+    /// Cap on the fee of any relayer, in basis points of the deposit size
+    pub max_fee_bps: u16,
+}
+
+impl Default for RelayerPolicy {
+    fn default() -> Self {
+        RelayerPolicy {
+            registration_required: false,
+            max_fee_bps: MAX_FEE_BPS,
+        }
+    }
+}
+
+/// Relayer, as declared by the relayer itself
+#[cw_serde]
+pub struct Relayer {
+    /// Fee the relayer charges, in basis points of the deposit size
+    pub fee_bps: u16,
+    pub metadata_url: String,
+}
+
+/// RelayerStats, withdrawals relayed by an address whether registered or not
+#[cw_serde]
+#[derive(Default)]
+pub struct RelayerStats {
+    pub withdrawals: u64,
+    /// Fees earned in the asset of each pool, ordered by pool id
+    pub fees_earned: Vec<PoolFee>,
+}
+
+impl RelayerStats {
+    pub fn record(&mut self, pool_id: u32, fee: Uint128) {
+        self.withdrawals += 1;
+        if fee.is_zero() {
+            return;
+        }
+        match self
+            .fees_earned
+            .binary_search_by_key(&pool_id, |f| f.pool_id)
+        {
+            Ok(i) => self.fees_earned[i].amount += fee,
+            Err(i) => self.fees_earned.insert(
+                i,
+                PoolFee {
+                    pool_id,
+                    amount: fee,
+                },
+            ),
+        }
+    }
+}
+
+#[cw_serde]
+pub struct PoolFee {
+    pub pool_id: u32,
+    pub amount: Uint128,
+}
+
+/// Mixer, a fixed size deposit pool
 #[cw_serde]
 pub struct Mixer {
     pub deposit_size: Uint128,
-    pub native_token_denom: String,
-    pub vk_raw: Binary,
+    pub asset: AssetInfo,
     pub merkle_tree: MerkleTree,
 }
 
+/// RootInfo, when a root of the history was created
+#[cw_serde]
+pub struct RootInfo {
+    pub leaf_count: u32,
+    pub height: u64,
+    pub time: Timestamp,
+}
+
 /// MerkleTree
 #[cw_serde]
 pub struct MerkleTree {
     pub levels: u32,
     pub current_root_index: u32,
     pub next_index: u32,
+    /// Id of the `Curve`
     pub curve: u8,
+    /// A full tree rolls over into a new one of the next epoch
+    #[serde(default)]
+    pub epoch: u32,
+    /// Number of recent roots a withdraw proof can be made against
+    #[serde(default = "default_root_history_size")]
+    pub root_history_size: u32,
+}
+
+fn default_root_history_size() -> u32 {
+    DEFAULT_ROOT_HISTORY_SIZE
 }
 
 impl MerkleTree {
@@ -36,21 +191,81 @@ impl MerkleTree {
     ) -> Result<[u8; 32], ContractError> {
         match api.poseidon_hash(left, right, self.curve) {
             Ok(hash) => Ok(element_encoder(&hash)),
-            Err(err) => Err(ContractError::Std(err)),
+            Err(_) => Err(ContractError::HashError),
         }
     }
 
+    /// Hash and store the empty subtree of every level, from the empty leaf up to the empty root
+    pub fn init_zeroes(
+        &self,
+        api: &dyn Api,
+        pool_id: u32,
+        zero_leaf: [u8; 32],
+        store: &mut dyn Storage,
+    ) -> Result<(), ContractError> {
+        let mut zero = zero_leaf;
+        save_zero(store, pool_id, 0, &zero);
+        for i in 1..=self.levels {
+            zero = self.hash_left_right(api, &zero, &zero)?;
+            save_zero(store, pool_id, i, &zero);
+        }
+        Ok(())
+    }
+
+    /// Write the empty tree of the current epoch
+    pub fn init(&self, pool_id: u32, block: &BlockInfo, store: &mut dyn Storage) -> StdResult<()> {
+        for i in 0..self.levels {
+            let zero = read_zero(store, pool_id, i);
+            save_subtree(store, pool_id, self.epoch, i, &zero);
+        }
+        let zero_root = read_zero(store, pool_id, self.levels);
+        save_root(store, pool_id, self.epoch, 0, &zero_root);
+        save_root_info(
+            store,
+            pool_id,
+            self.epoch,
+            0,
+            &RootInfo {
+                leaf_count: 0,
+                height: block.height,
+                time: block.time,
+            },
+        )
+    }
+
+    // the full tree is archived so its roots stay withdrawable
+    fn roll_over(
+        &mut self,
+        pool_id: u32,
+        block: &BlockInfo,
+        store: &mut dyn Storage,
+    ) -> Result<(), ContractError> {
+        epoch_tree_write(store, pool_id, self)?;
+        self.epoch = self
+            .epoch
+            .checked_add(1)
+            .ok_or(ContractError::MerkleTreeIsFull)?;
+        self.current_root_index = 0;
+        self.next_index = 0;
+        self.init(pool_id, block, store)?;
+        Ok(())
+    }
+
     pub fn insert(
         &mut self,
         api: &dyn Api,
+        pool_id: u32,
         leaf: [u8; 32],
+        block: &BlockInfo,
         store: &mut dyn Storage,
     ) -> Result<u32, ContractError> {
+        if u64::from(self.next_index) == 1u64 << self.levels {
+            self.roll_over(pool_id, block, store)?;
+        }
         let next_index = self.next_index;
-        assert!(
-            next_index != 2u32.pow(self.levels as u32),
-            "Merkle tree is full"
-        );
+        let epoch = self.epoch;
+
+        save_commitment(store, pool_id, epoch, next_index, &leaf);
 
         let mut current_index = next_index;
         let mut current_level_hash = leaf;
@@ -60,88 +275,472 @@ impl MerkleTree {
         for i in 0..self.levels {
             if current_index % 2 == 0 {
                 left = current_level_hash;
-                right = zeroes::zeroes(i);
-                save_subtree(store, i, &current_level_hash);
+                right = read_zero(store, pool_id, i);
+                save_subtree(store, pool_id, epoch, i, &current_level_hash);
             } else {
-                left = read_subtree(store, i)?;
+                left = read_subtree(store, pool_id, epoch, i)?;
                 right = current_level_hash;
             }
 
             current_level_hash = self.hash_left_right(api, &left, &right)?;
             current_index /= 2;
+            // keep the internal nodes so paths can be served, the top one is the root
+            if i + 1 < self.levels {
+                save_node(
+                    store,
+                    pool_id,
+                    epoch,
+                    i + 1,
+                    current_index,
+                    &current_level_hash,
+                );
+            }
         }
 
-        let new_root_index = (self.current_root_index + 1) % ROOT_HISTORY_SIZE;
+        let new_root_index = (self.current_root_index + 1) % self.root_history_size;
         self.current_root_index = new_root_index;
-        save_root(store, new_root_index, &current_level_hash);
+        save_root(store, pool_id, epoch, new_root_index, &current_level_hash);
         self.next_index = next_index + 1;
+        save_root_info(
+            store,
+            pool_id,
+            epoch,
+            new_root_index,
+            &RootInfo {
+                leaf_count: self.next_index,
+                height: block.height,
+                time: block.time,
+            },
+        )?;
         Ok(next_index)
     }
 
-    pub fn is_known_root(&self, root: [u8; 32], store: &dyn Storage) -> bool {
+    pub fn is_known_root(&self, pool_id: u32, root: [u8; 32], store: &dyn Storage) -> bool {
         if root == DEFAULT_LEAF {
             return false;
         }
 
-        let mut i = self.current_root_index;
-        for _ in 0..ROOT_HISTORY_SIZE {
-            let r = read_root(store, i);
-            if r == root {
-                return true;
-            }
+        read_root_index(store, pool_id, self.epoch, &root).is_some()
+    }
 
-            if i == 0 {
-                i = ROOT_HISTORY_SIZE - 1;
-            } else {
-                i -= 1;
+    /// Move the newest roots into a ring of `size` slots, the oldest ones are dropped when it shrinks
+    pub fn resize_root_history(
+        &mut self,
+        pool_id: u32,
+        size: u32,
+        store: &mut dyn Storage,
+    ) -> StdResult<()> {
+        let old_size = self.root_history_size;
+        // slot 0 holds the empty tree root, so a ring is full after `old_size - 1` inserts
+        let kept = old_size.min(size).min(self.next_index.saturating_add(1));
+
+        let mut roots = vec![];
+        for age in 0..kept {
+            let index = (self.current_root_index + old_size - age) % old_size;
+            roots.push((
+                read_root(store, pool_id, self.epoch, index),
+                read_root_info(store, pool_id, self.epoch, index)?,
+            ));
+        }
+
+        for index in 0..old_size {
+            remove_root(store, pool_id, self.epoch, index);
+        }
+        // the newest root goes to the last kept slot, so the ring keeps growing from there
+        for (age, (root, info)) in roots.iter().enumerate() {
+            let index = kept - 1 - age as u32;
+            save_root(store, pool_id, self.epoch, index, root);
+            if let Some(info) = info {
+                save_root_info(store, pool_id, self.epoch, index, info)?;
             }
         }
 
-        false
+        self.current_root_index = kept - 1;
+        self.root_history_size = size;
+        Ok(())
     }
 }
 
-pub fn save_subtree(store: &mut dyn Storage, k: u32, data: &[u8; 32]) {
-    prefixed(store, FILLED_SUBTREES_KEY).set(&k.to_le_bytes(), data)
+pub fn save_zero(store: &mut dyn Storage, pool_id: u32, level: u32, data: &[u8; 32]) {
+    PrefixedStorage::multilevel(store, &[ZEROES_KEY, &pool_id.to_be_bytes()])
+        .set(&level.to_le_bytes(), data)
 }
 
-pub fn read_subtree(store: &dyn Storage, k: u32) -> Result<[u8; 32], ContractError> {
-    prefixed_read(store, FILLED_SUBTREES_KEY)
-        .get(&k.to_le_bytes())
+/// Empty subtree of the level, every epoch of a pool shares them
+pub fn read_zero(store: &dyn Storage, pool_id: u32, level: u32) -> [u8; 32] {
+    ReadonlyPrefixedStorage::multilevel(store, &[ZEROES_KEY, &pool_id.to_be_bytes()])
+        .get(&level.to_le_bytes())
         .map(|item| element_encoder(&item))
-        .ok_or(ContractError::ItemNotFound {})
+        // pools instantiated before zeroes were stored are BN254 trees of at most 30 levels
+        .unwrap_or_else(|| zeroes::zeroes(level))
 }
 
-pub fn save_root(store: &mut dyn Storage, k: u32, data: &[u8; 32]) {
-    prefixed(store, MERKLE_ROOTS_KEY).set(&k.to_le_bytes(), data)
+pub fn save_subtree(store: &mut dyn Storage, pool_id: u32, epoch: u32, k: u32, data: &[u8; 32]) {
+    PrefixedStorage::multilevel(
+        store,
+        &[FILLED_SUBTREES_KEY, &tree_namespace(pool_id, epoch)],
+    )
+    .set(&k.to_le_bytes(), data)
 }
 
-pub fn read_root(store: &dyn Storage, k: u32) -> [u8; 32] {
-    prefixed_read(store, MERKLE_ROOTS_KEY)
+pub fn read_subtree(
+    store: &dyn Storage,
+    pool_id: u32,
+    epoch: u32,
+    k: u32,
+) -> Result<[u8; 32], ContractError> {
+    ReadonlyPrefixedStorage::multilevel(
+        store,
+        &[FILLED_SUBTREES_KEY, &tree_namespace(pool_id, epoch)],
+    )
+    .get(&k.to_le_bytes())
+    .map(|item| element_encoder(&item))
+    .ok_or(ContractError::ItemNotFound {})
+}
+
+// the root it replaces in the slot is evicted from the index
+pub fn save_root(store: &mut dyn Storage, pool_id: u32, epoch: u32, k: u32, data: &[u8; 32]) {
+    unindex_root(store, pool_id, epoch, k);
+    PrefixedStorage::multilevel(store, &[MERKLE_ROOTS_KEY, &tree_namespace(pool_id, epoch)])
+        .set(&k.to_le_bytes(), data);
+    index_root(store, pool_id, epoch, k, data);
+}
+
+pub fn read_root(store: &dyn Storage, pool_id: u32, epoch: u32, k: u32) -> [u8; 32] {
+    ReadonlyPrefixedStorage::multilevel(store, &[MERKLE_ROOTS_KEY, &tree_namespace(pool_id, epoch)])
         .get(&k.to_le_bytes())
         .map(|item| element_encoder(&item))
         .unwrap_or(DEFAULT_LEAF)
 }
 
-pub fn mixer_write(storage: &mut dyn Storage, data: &Mixer) -> StdResult<()> {
-    singleton(storage, MIXER_KEY).save(data)
+// the root and its info
+fn remove_root(store: &mut dyn Storage, pool_id: u32, epoch: u32, k: u32) {
+    unindex_root(store, pool_id, epoch, k);
+    PrefixedStorage::multilevel(store, &[MERKLE_ROOTS_KEY, &tree_namespace(pool_id, epoch)])
+        .remove(&k.to_le_bytes());
+    Bucket::<RootInfo>::multilevel(store, &[ROOT_INFOS_KEY, &tree_namespace(pool_id, epoch)])
+        .remove(&k.to_le_bytes());
+}
+
+fn index_root(store: &mut dyn Storage, pool_id: u32, epoch: u32, k: u32, root: &[u8; 32]) {
+    PrefixedStorage::multilevel(store, &[ROOT_INDEXES_KEY, &tree_namespace(pool_id, epoch)])
+        .set(root, &k.to_be_bytes());
+}
+
+// a root may have been saved again in a newer slot, whose entry is kept
+fn unindex_root(store: &mut dyn Storage, pool_id: u32, epoch: u32, k: u32) {
+    let root = read_root(store, pool_id, epoch, k);
+    if read_root_index(store, pool_id, epoch, &root) == Some(k) {
+        PrefixedStorage::multilevel(store, &[ROOT_INDEXES_KEY, &tree_namespace(pool_id, epoch)])
+            .remove(&root);
+    }
+}
+
+/// Slot of a root in the history ring, None once it has been evicted
+pub fn read_root_index(
+    store: &dyn Storage,
+    pool_id: u32,
+    epoch: u32,
+    root: &[u8; 32],
+) -> Option<u32> {
+    ReadonlyPrefixedStorage::multilevel(store, &[ROOT_INDEXES_KEY, &tree_namespace(pool_id, epoch)])
+        .get(root)
+        .map(|v| {
+            let mut index = [0u8; 4];
+            index.copy_from_slice(&v);
+            u32::from_be_bytes(index)
+        })
+}
+
+pub fn save_root_info(
+    store: &mut dyn Storage,
+    pool_id: u32,
+    epoch: u32,
+    k: u32,
+    data: &RootInfo,
+) -> StdResult<()> {
+    Bucket::multilevel(store, &[ROOT_INFOS_KEY, &tree_namespace(pool_id, epoch)])
+        .save(&k.to_le_bytes(), data)
+}
+
+// roots migrated from 0.1.0 have no info
+pub fn read_root_info(
+    store: &dyn Storage,
+    pool_id: u32,
+    epoch: u32,
+    k: u32,
+) -> StdResult<Option<RootInfo>> {
+    ReadonlyBucket::multilevel(store, &[ROOT_INFOS_KEY, &tree_namespace(pool_id, epoch)])
+        .may_load(&k.to_le_bytes())
+}
+
+pub fn save_commitment(
+    store: &mut dyn Storage,
+    pool_id: u32,
+    epoch: u32,
+    index: u32,
+    leaf: &[u8; 32],
+) {
+    PrefixedStorage::multilevel(store, &[COMMITMENTS_KEY, &tree_namespace(pool_id, epoch)])
+        .set(&index.to_be_bytes(), leaf);
+    PrefixedStorage::multilevel(
+        store,
+        &[COMMITMENT_INDEXES_KEY, &tree_namespace(pool_id, epoch)],
+    )
+    .set(leaf, &index.to_be_bytes());
+    PrefixedStorage::multilevel(store, &[DEPOSITED_COMMITMENTS_KEY, &pool_id.to_be_bytes()])
+        .set(leaf, &[1u8]);
+}
+
+/// Whether the commitment is in any tree of the pool
+pub fn is_deposited(store: &dyn Storage, pool_id: u32, leaf: &[u8; 32]) -> bool {
+    ReadonlyPrefixedStorage::multilevel(store, &[DEPOSITED_COMMITMENTS_KEY, &pool_id.to_be_bytes()])
+        .get(leaf)
+        .is_some()
+}
+
+pub fn read_commitments(
+    store: &dyn Storage,
+    pool_id: u32,
+    epoch: u32,
+    start_after: Option<u32>,
+    limit: usize,
+) -> Vec<(u32, [u8; 32])> {
+    let start = match start_after {
+        Some(u32::MAX) => return vec![],
+        Some(index) => Some((index + 1).to_be_bytes()),
+        None => None,
+    };
+    ReadonlyPrefixedStorage::multilevel(store, &[COMMITMENTS_KEY, &tree_namespace(pool_id, epoch)])
+        .range(start.as_ref().map(|k| k.as_slice()), None, Order::Ascending)
+        .take(limit)
+        .map(|(k, v)| {
+            let mut index = [0u8; 4];
+            index.copy_from_slice(&k);
+            (u32::from_be_bytes(index), element_encoder(&v))
+        })
+        .collect()
+}
+
+pub fn read_commitment_index(
+    store: &dyn Storage,
+    pool_id: u32,
+    epoch: u32,
+    leaf: &[u8; 32],
+) -> Option<u32> {
+    ReadonlyPrefixedStorage::multilevel(
+        store,
+        &[COMMITMENT_INDEXES_KEY, &tree_namespace(pool_id, epoch)],
+    )
+    .get(leaf)
+    .map(|v| {
+        let mut index = [0u8; 4];
+        index.copy_from_slice(&v);
+        u32::from_be_bytes(index)
+    })
+}
+
+pub fn save_node(
+    store: &mut dyn Storage,
+    pool_id: u32,
+    epoch: u32,
+    level: u32,
+    index: u32,
+    data: &[u8; 32],
+) {
+    PrefixedStorage::multilevel(store, &[NODES_KEY, &tree_namespace(pool_id, epoch)])
+        .set(&node_key(level, index), data)
+}
+
+// level 0 nodes are the stored commitments
+pub fn read_node(
+    store: &dyn Storage,
+    pool_id: u32,
+    epoch: u32,
+    level: u32,
+    index: u32,
+) -> Option<[u8; 32]> {
+    let node = if level == 0 {
+        ReadonlyPrefixedStorage::multilevel(
+            store,
+            &[COMMITMENTS_KEY, &tree_namespace(pool_id, epoch)],
+        )
+        .get(&index.to_be_bytes())
+    } else {
+        ReadonlyPrefixedStorage::multilevel(store, &[NODES_KEY, &tree_namespace(pool_id, epoch)])
+            .get(&node_key(level, index))
+    };
+    node.map(|item| element_encoder(&item))
+}
+
+fn node_key(level: u32, index: u32) -> Vec<u8> {
+    [level.to_be_bytes(), index.to_be_bytes()].concat()
+}
+
+// epoch 0 keeps the namespace pools used before trees rolled over
+fn tree_namespace(pool_id: u32, epoch: u32) -> Vec<u8> {
+    if epoch == 0 {
+        pool_id.to_be_bytes().to_vec()
+    } else {
+        [pool_id.to_be_bytes(), epoch.to_be_bytes()].concat()
+    }
+}
+
+pub fn epoch_tree_write(
+    storage: &mut dyn Storage,
+    pool_id: u32,
+    data: &MerkleTree,
+) -> StdResult<()> {
+    Bucket::multilevel(storage, &[EPOCH_TREES_KEY, &pool_id.to_be_bytes()])
+        .save(&data.epoch.to_be_bytes(), data)
+}
+pub fn epoch_tree_read(storage: &dyn Storage, pool_id: u32, epoch: u32) -> StdResult<MerkleTree> {
+    ReadonlyBucket::multilevel(storage, &[EPOCH_TREES_KEY, &pool_id.to_be_bytes()])
+        .load(&epoch.to_be_bytes())
+}
+
+pub fn config_write(storage: &mut dyn Storage, data: &Config) -> StdResult<()> {
+    singleton(storage, CONFIG_KEY).save(data)
+}
+pub fn config_read(storage: &dyn Storage) -> StdResult<Config> {
+    singleton_read(storage, CONFIG_KEY).load()
+}
+
+// stored under the raw cw2 key so generic tooling can read it
+pub fn set_contract_version(
+    storage: &mut dyn Storage,
+    contract: &str,
+    version: &str,
+) -> StdResult<()> {
+    let data = ContractVersion {
+        contract: contract.to_string(),
+        version: version.to_string(),
+    };
+    storage.set(CONTRACT_INFO_KEY, &to_vec(&data)?);
+    Ok(())
+}
+pub fn get_contract_version(storage: &dyn Storage) -> StdResult<Option<ContractVersion>> {
+    storage
+        .get(CONTRACT_INFO_KEY)
+        .map(|data| from_slice(&data))
+        .transpose()
+}
+
+pub fn pause_status_write(storage: &mut dyn Storage, data: &PauseStatus) -> StdResult<()> {
+    singleton(storage, PAUSE_STATUS_KEY).save(data)
+}
+pub fn pause_status_read(storage: &dyn Storage) -> StdResult<PauseStatus> {
+    singleton_read(storage, PAUSE_STATUS_KEY)
+        .may_load()
+        .map(Option::unwrap_or_default)
+}
+
+pub fn relayer_policy_write(storage: &mut dyn Storage, data: &RelayerPolicy) -> StdResult<()> {
+    singleton(storage, RELAYER_POLICY_KEY).save(data)
+}
+pub fn relayer_policy_read(storage: &dyn Storage) -> StdResult<RelayerPolicy> {
+    singleton_read(storage, RELAYER_POLICY_KEY)
+        .may_load()
+        .map(Option::unwrap_or_default)
+}
+
+pub fn relayer_write(storage: &mut dyn Storage, address: &Addr, data: &Relayer) -> StdResult<()> {
+    bucket(storage, RELAYERS_KEY).save(address.as_bytes(), data)
+}
+pub fn relayer_remove(storage: &mut dyn Storage, address: &Addr) {
+    bucket::<Relayer>(storage, RELAYERS_KEY).remove(address.as_bytes())
+}
+pub fn relayer_read(storage: &dyn Storage, address: &Addr) -> StdResult<Option<Relayer>> {
+    bucket_read(storage, RELAYERS_KEY).may_load(address.as_bytes())
+}
+/// Registered relayers ordered by address
+pub fn relayers_read(
+    storage: &dyn Storage,
+    start_after: Option<&Addr>,
+    limit: usize,
+) -> StdResult<Vec<(Addr, Relayer)>> {
+    // the smallest key after `start_after`
+    let start = start_after.map(|addr| [addr.as_bytes(), &[0u8]].concat());
+    bucket_read(storage, RELAYERS_KEY)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (k, relayer) = item?;
+            Ok((Addr::unchecked(String::from_utf8(k)?), relayer))
+        })
+        .collect()
+}
+
+pub fn relayer_stats_write(
+    storage: &mut dyn Storage,
+    address: &Addr,
+    data: &RelayerStats,
+) -> StdResult<()> {
+    bucket(storage, RELAYER_STATS_KEY).save(address.as_bytes(), data)
+}
+pub fn relayer_stats_read(storage: &dyn Storage, address: &Addr) -> StdResult<RelayerStats> {
+    bucket_read(storage, RELAYER_STATS_KEY)
+        .may_load(address.as_bytes())
+        .map(Option::unwrap_or_default)
+}
+
+pub fn mixer_write(storage: &mut dyn Storage, pool_id: u32, data: &Mixer) -> StdResult<()> {
+    bucket(storage, MIXERS_KEY).save(&pool_id.to_be_bytes(), data)
+}
+pub fn mixer_read(storage: &dyn Storage, pool_id: u32) -> StdResult<Mixer> {
+    bucket_read(storage, MIXERS_KEY).load(&pool_id.to_be_bytes())
 }
-pub fn mixer_read(storage: &dyn Storage) -> StdResult<Mixer> {
-    singleton_read(storage, MIXER_KEY).load()
+pub fn mixers_read(storage: &dyn Storage) -> StdResult<Vec<(u32, Mixer)>> {
+    bucket_read(storage, MIXERS_KEY)
+        .range(None, None, Order::Ascending)
+        .map(|item| {
+            let (k, mixer) = item?;
+            let mut pool_id = [0u8; 4];
+            pool_id.copy_from_slice(&k);
+            Ok((u32::from_be_bytes(pool_id), mixer))
+        })
+        .collect()
 }
 
-pub fn nullifier_write(storage: &mut dyn Storage, hash: &[u8; 32]) {
-    prefixed(storage, USED_NULLIFIERS_KEY).set(hash, &[1u8])
+pub fn nullifier_write(storage: &mut dyn Storage, pool_id: u32, hash: &[u8; 32]) {
+    PrefixedStorage::multilevel(storage, &[USED_NULLIFIERS_KEY, &pool_id.to_be_bytes()])
+        .set(hash, &[1u8])
 }
-pub fn nullifier_read(storage: &dyn Storage, hash: &[u8; 32]) -> bool {
-    prefixed_read(storage, USED_NULLIFIERS_KEY)
+pub fn nullifier_read(storage: &dyn Storage, pool_id: u32, hash: &[u8; 32]) -> bool {
+    ReadonlyPrefixedStorage::multilevel(storage, &[USED_NULLIFIERS_KEY, &pool_id.to_be_bytes()])
         .get(hash)
         .is_some()
+        // nullifiers spent before 0.1.0 was migrated are not namespaced
+        || (pool_id == LEGACY_POOL_ID
+            && ReadonlyPrefixedStorage::new(storage, USED_NULLIFIERS_KEY)
+                .get(hash)
+                .is_some())
 }
 
-// put the length bytes at the first for compatibility with legacy singleton store
-pub const MIXER_KEY: &[u8] = b"mixer";
+pub const CONTRACT_INFO_KEY: &[u8] = b"contract_info";
+pub const CONFIG_KEY: &[u8] = b"config";
+pub const PAUSE_STATUS_KEY: &[u8] = b"pause_status";
+pub const RELAYER_POLICY_KEY: &[u8] = b"relayer_policy";
+pub const RELAYERS_KEY: &[u8] = b"relayers";
+pub const RELAYER_STATS_KEY: &[u8] = b"relayer_stats";
+// pools are keyed by their big endian pool id, so ranging over them is ordered
+pub const MIXERS_KEY: &[u8] = b"mixers";
 
+// tree stores are namespaced by pool id and epoch, nullifier stores by pool id
 pub const MERKLE_ROOTS_KEY: &[u8] = b"merkle_roots";
+pub const ROOT_INFOS_KEY: &[u8] = b"root_infos";
+// root -> slot of the root history, namespaced like the roots
+pub const ROOT_INDEXES_KEY: &[u8] = b"root_indexes";
 pub const FILLED_SUBTREES_KEY: &[u8] = b"filled_subtrees";
 pub const USED_NULLIFIERS_KEY: &[u8] = b"used_nullifers";
+// leaves are keyed by their big endian index, with a reverse lookup by commitment
+pub const COMMITMENTS_KEY: &[u8] = b"commitments";
+pub const COMMITMENT_INDEXES_KEY: &[u8] = b"commitment_indexes";
+// commitments of every epoch, namespaced by pool id
+pub const DEPOSITED_COMMITMENTS_KEY: &[u8] = b"deposited_commitments";
+// internal nodes are keyed by big endian level then index
+pub const NODES_KEY: &[u8] = b"nodes";
+// level -> empty subtree, namespaced by pool id
+pub const ZEROES_KEY: &[u8] = b"zeroes";
+// full trees of past epochs, keyed by big endian epoch
+pub const EPOCH_TREES_KEY: &[u8] = b"epoch_trees";
@@ -1,13 +1,14 @@
-use crate::msg::InstantiateMsg;
+use crate::asset::AssetInfoMsg;
+use crate::msg::{InstantiateMsg, PoolMsg};
 use crate::test_util::{gen_zk_proof, MixerR1CSProverBn254_30};
 
-use cosmwasm_std::{coins, from_slice, to_vec, Binary, ContractResult, QueryResponse};
-use cosmwasm_vm::testing::{mock_backend, mock_env, mock_info, MockApi};
+use cosmwasm_std::{coins, from_slice, to_vec, Binary, ContractResult, QueryResponse, Response};
+use cosmwasm_vm::testing::{mock_backend, mock_env, mock_info, MockApi, MockQuerier, MockStorage};
 use cosmwasm_vm::{
     call_execute_raw, call_instantiate_raw, call_query_raw, Instance, InstanceOptions, Size,
 };
 
-use crate::utils::truncate_and_pad;
+use crate::utils::{encode_address, encode_domain, encode_expiry};
 
 use arkworks_setups::common::MixerProof;
 use arkworks_setups::{Curve, MixerProver};
@@ -49,6 +50,8 @@ fn gen_commitment(note_secret: &[u8]) -> Vec<u8> {
 fn gen_zk(note_secret: &[u8], index: u64, leaves: Vec<Vec<u8>>) -> MixerProof {
     let secret = note_secret[0..32].to_vec();
     let nullifier = note_secret[32..64].to_vec();
+    // the instance is executed with the same mock env
+    let env = mock_env();
 
     // Setup zk circuit for withdraw
     gen_zk_proof(
@@ -57,15 +60,19 @@ fn gen_zk(note_secret: &[u8], index: u64, leaves: Vec<Vec<u8>>) -> MixerProof {
         nullifier,
         index,
         leaves,
-        truncate_and_pad(RECIPIENT.as_bytes()),
-        truncate_and_pad(SENDER.as_bytes()),
+        encode_address(RECIPIENT),
+        [
+            encode_address(SENDER),
+            encode_domain(&env.block.chain_id, env.contract.address.as_str()),
+            encode_expiry(None),
+        ]
+        .concat(),
         0u128,
         0u128,
     )
 }
 
-#[test]
-fn test_zk() {
+fn setup_instance(root_history_size: Option<u32>) -> Instance<MockApi, MockStorage, MockQuerier> {
     let mut backend = mock_backend(&[]);
     backend.api = MockApi::new(24); // same as old version
     let mut instance = Instance::from_code(
@@ -77,11 +84,21 @@ fn test_zk() {
     .unwrap();
 
     let msg = to_vec(&InstantiateMsg {
-        deposit_size: 100000u128.into(),
+        admin: None,
         merkletree_levels: 30,
-        native_token_denom: "orai".to_string(),
-        curve: 1,
+        curve: crate::msg::Curve::Bn254,
+        zero_leaf: None,
         vk_raw: VK_BYTES.into(),
+        vk_update_delay: 86400,
+        excess_funds: None,
+        refund_denom: None,
+        root_history_size,
+        pools: vec![PoolMsg {
+            deposit_size: 100000u128.into(),
+            asset: AssetInfoMsg::NativeToken {
+                denom: "orai".to_string(),
+            },
+        }],
     })
     .unwrap();
     let env = to_vec(&mock_env()).unwrap();
@@ -91,21 +108,35 @@ fn test_zk() {
         "Done instantiating contract: {}",
         String::from_utf8(contract_result).unwrap()
     );
+    instance
+}
+
+fn deposit(instance: &mut Instance<MockApi, MockStorage, MockQuerier>, commitment: &Binary) {
+    let env = to_vec(&mock_env()).unwrap();
+    let info = to_vec(&mock_info("creator", &coins(100000, "orai"))).unwrap();
+    let msg = format!(
+        r#"{{"deposit":{{"pool_id":0,"commitment": "{}"}}}}"#,
+        commitment
+    )
+    .into_bytes();
+    println!("{}", String::from_utf8_lossy(&msg));
+    let contract_result = call_execute_raw(instance, &env, &info, &msg).unwrap();
+    println!(
+        "Done excuting deposit: {}",
+        String::from_utf8(contract_result).unwrap()
+    );
+}
+
+#[test]
+fn test_zk() {
+    let mut instance = setup_instance(None);
 
     let mut leaves = vec![];
     for note in NOTES {
         let note_secret = hex::decode(note).unwrap();
         let commitment_hash = Binary::from(gen_commitment(&note_secret));
         leaves.push(commitment_hash.to_vec());
-        let env = to_vec(&mock_env()).unwrap();
-        let info = to_vec(&mock_info("creator", &coins(100000, "orai"))).unwrap();
-        let msg = format!(r#"{{"deposit":{{"commitment": "{}"}}}}"#, commitment_hash).into_bytes();
-        println!("{}", String::from_utf8_lossy(&msg));
-        let contract_result = call_execute_raw(&mut instance, &env, &info, &msg).unwrap();
-        println!(
-            "Done excuting deposit: {}",
-            String::from_utf8(contract_result).unwrap()
-        );
+        deposit(&mut instance, &commitment_hash);
     }
 
     // withdraw the first deposit
@@ -115,7 +146,7 @@ fn test_zk() {
     let env = to_vec(&mock_env()).unwrap();
     let info = to_vec(&mock_info("anyone", &[])).unwrap();
     let msg = format!(
-        r#"{{"withdraw":{{"proof_bytes": "{}","root": "{}","nullifier_hash": "{}","recipient": "{}","relayer":"{}","fee":"0","refund":"0"}}}}"#,
+        r#"{{"withdraw":{{"pool_id":0,"proof_bytes": "{}","root": "{}","nullifier_hash": "{}","recipient": "{}","relayer":"{}","fee":"0","refund":"0"}}}}"#,
         Binary::from(proof.proof),Binary::from(proof.root_raw),Binary::from(proof.nullifier_hash_raw) ,RECIPIENT,SENDER,
     )
     .into_bytes();
@@ -128,7 +159,7 @@ fn test_zk() {
 
     // query
     let env = to_vec(&mock_env()).unwrap();
-    let msg = br#"{"merkle_tree_info":{}}"#;
+    let msg = br#"{"merkle_tree_info":{"pool_id":0}}"#;
     let data = call_query_raw(&mut instance, &env, msg).unwrap();
     let contract_result: ContractResult<QueryResponse> = from_slice(&data).unwrap();
     println!(
@@ -136,3 +167,40 @@ fn test_zk() {
         String::from_utf8(contract_result.unwrap().to_vec()).unwrap()
     );
 }
+
+// gas of a withdraw rejected at the known root check, after `deposits` deposits
+fn unknown_root_withdraw_gas(root_history_size: u32, deposits: usize) -> u64 {
+    let mut instance = setup_instance(Some(root_history_size));
+    for note in NOTES.iter().cycle().take(deposits) {
+        let note_secret = hex::decode(note).unwrap();
+        deposit(&mut instance, &Binary::from(gen_commitment(&note_secret)));
+    }
+
+    let env = to_vec(&mock_env()).unwrap();
+    let info = to_vec(&mock_info("anyone", &[])).unwrap();
+    let msg = format!(
+        r#"{{"withdraw":{{"pool_id":0,"proof_bytes": "{}","root": "{}","nullifier_hash": "{}","recipient": "{}","relayer":"{}","fee":"0","refund":"0"}}}}"#,
+        Binary::from([1u8; 32]),Binary::from([2u8; 32]),Binary::from([3u8; 32]),RECIPIENT,SENDER,
+    )
+    .into_bytes();
+    let gas_before = instance.get_gas_left();
+    let data = call_execute_raw(&mut instance, &env, &info, &msg).unwrap();
+    let gas_used = gas_before - instance.get_gas_left();
+
+    let contract_result: ContractResult<Response> = from_slice(&data).unwrap();
+    assert!(contract_result
+        .unwrap_err()
+        .contains("[3008] Root is not known"));
+    gas_used
+}
+
+#[test]
+fn test_known_root_check_gas() {
+    // a full ring of 10 roots against one of 1000 roots
+    let short_history = unknown_root_withdraw_gas(10, 10);
+    let long_history = unknown_root_withdraw_gas(1000, 10);
+    println!("Unknown root withdraw gas, history of 10: {short_history}, of 1000: {long_history}");
+
+    // scanning the ring would read 990 more slots, the index reads one
+    assert!(long_history < short_history + short_history / 10);
+}
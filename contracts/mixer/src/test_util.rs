@@ -1,4 +1,8 @@
+use std::sync::OnceLock;
+
+use ark_bls12_381::Bls12_381;
 use ark_bn254::Bn254;
+use arkworks_setups::common::setup_keys_unchecked;
 use arkworks_setups::common::Leaf;
 use arkworks_setups::common::MixerProof;
 use arkworks_setups::r1cs::mixer::MixerR1CSProver;
@@ -10,6 +14,7 @@ use crate::zeroes::DEFAULT_LEAF;
 pub const MERKLE_TREE_LEVELS: usize = 30;
 
 pub type MixerR1CSProverBn254_30 = MixerR1CSProver<Bn254, MERKLE_TREE_LEVELS>;
+pub type MixerR1CSProverBls381_30 = MixerR1CSProver<Bls12_381, MERKLE_TREE_LEVELS>;
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct Element(pub [u8; 32]);
@@ -24,6 +29,19 @@ impl Element {
 
 const PK_BYTES: &[u8; 3034288] = include_bytes!("../../../bn254/x5/proving_key.bin");
 
+/// Proving and verifying keys of the BLS12-381 circuit, there are no prebuilt ones
+/// so they are set up once per test run
+pub fn bls381_keys() -> &'static (Vec<u8>, Vec<u8>) {
+    static KEYS: OnceLock<(Vec<u8>, Vec<u8>)> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        let rng = &mut ark_std::test_rng();
+        let (circuit, ..) =
+            MixerR1CSProverBls381_30::setup_random_circuit(Curve::Bls381, DEFAULT_LEAF, rng)
+                .unwrap();
+        setup_keys_unchecked::<Bls12_381, _, _>(circuit, rng).unwrap()
+    })
+}
+
 pub fn setup_zk_circuit(
     index: u64,
     curve: Curve,
@@ -39,45 +57,42 @@ pub fn setup_zk_circuit(
 ) {
     let rng = &mut ark_std::test_rng();
 
-    match curve {
-        Curve::Bn254 => {
-            // fit inputs to the curve.
-            let Leaf {
-                secret_bytes,
-                nullifier_bytes,
-                leaf_bytes,
-                nullifier_hash_bytes,
-                ..
-            } = MixerR1CSProverBn254_30::create_random_leaf(curve, rng).unwrap();
+    // fit inputs to the curve.
+    let Leaf {
+        secret_bytes,
+        nullifier_bytes,
+        leaf_bytes,
+        nullifier_hash_bytes,
+        ..
+    } = match curve {
+        Curve::Bn254 => MixerR1CSProverBn254_30::create_random_leaf(curve, rng),
+        Curve::Bls381 => MixerR1CSProverBls381_30::create_random_leaf(curve, rng),
+    }
+    .unwrap();
 
-            let leaves = vec![leaf_bytes.clone()];
-            let proof = gen_zk_proof(
-                curve,
-                secret_bytes,
-                nullifier_bytes,
-                index,
-                leaves,
-                recipient_bytes,
-                relayer_bytes,
-                fee_value,
-                refund_value,
-            );
+    let leaves = vec![leaf_bytes.clone()];
+    let proof = gen_zk_proof(
+        curve,
+        secret_bytes,
+        nullifier_bytes,
+        index,
+        leaves,
+        recipient_bytes,
+        relayer_bytes,
+        fee_value,
+        refund_value,
+    );
 
-            let leaf_element = Element::from_bytes(&leaf_bytes);
-            let nullifier_hash_element = Element::from_bytes(&nullifier_hash_bytes);
-            let root_element = Element::from_bytes(&proof.root_raw);
+    let leaf_element = Element::from_bytes(&leaf_bytes);
+    let nullifier_hash_element = Element::from_bytes(&nullifier_hash_bytes);
+    let root_element = Element::from_bytes(&proof.root_raw);
 
-            (
-                proof.proof,
-                root_element,
-                nullifier_hash_element,
-                leaf_element,
-            )
-        }
-        Curve::Bls381 => {
-            unimplemented!()
-        }
-    }
+    (
+        proof.proof,
+        root_element,
+        nullifier_hash_element,
+        leaf_element,
+    )
 }
 
 pub fn gen_zk_proof(
@@ -92,19 +107,35 @@ pub fn gen_zk_proof(
     refund: u128,
 ) -> MixerProof {
     let rng = &mut ark_std::test_rng();
-    MixerR1CSProverBn254_30::create_proof(
-        curve,
-        secret,
-        nullifier,
-        leaves,
-        index,
-        recipient,
-        relayer,
-        fee,
-        refund,
-        PK_BYTES.to_vec(),
-        DEFAULT_LEAF,
-        rng,
-    )
+    match curve {
+        Curve::Bn254 => MixerR1CSProverBn254_30::create_proof(
+            curve,
+            secret,
+            nullifier,
+            leaves,
+            index,
+            recipient,
+            relayer,
+            fee,
+            refund,
+            PK_BYTES.to_vec(),
+            DEFAULT_LEAF,
+            rng,
+        ),
+        Curve::Bls381 => MixerR1CSProverBls381_30::create_proof(
+            curve,
+            secret,
+            nullifier,
+            leaves,
+            index,
+            recipient,
+            relayer,
+            fee,
+            refund,
+            bls381_keys().0.clone(),
+            DEFAULT_LEAF,
+            rng,
+        ),
+    }
     .unwrap()
 }
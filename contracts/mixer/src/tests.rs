@@ -9,17 +9,43 @@ use cosmwasm_std::testing::{
 };
 use cosmwasm_std::Api;
 use cosmwasm_std::Binary;
-use cosmwasm_std::{attr, Coin, OwnedDeps, Uint128};
+use cosmwasm_std::StdError;
+use cosmwasm_std::Storage;
+use cosmwasm_std::{
+    attr, from_binary, to_binary, Addr, BankMsg, Coin, CosmosMsg, OwnedDeps, Uint128, WasmMsg,
+};
+use cosmwasm_storage::{prefixed, prefixed_read, to_length_prefixed};
 
-use crate::contract::{execute, instantiate};
-use crate::msg::{DepositMsg, ExecuteMsg, InstantiateMsg, WithdrawMsg};
-use crate::state::read_root;
+use crate::asset::{AssetInfo, AssetInfoMsg};
+use crate::contract::{execute, instantiate, migrate, query};
+use crate::error::ContractError;
+use crate::migration::LEGACY_MIXER_KEY;
+use crate::msg::{
+    AreSpentResponse, BatchDepositMsg, CommitmentIndexResponse, CommitmentsResponse,
+    ConfigResponse, Cw20ExecuteMsg, Cw20HookMsg, Cw20ReceiveMsg, DepositMsg, ExcessFunds,
+    ExecuteMsg, InstantiateMsg, IsKnownRootResponse, IsSpentResponse, MerklePathResponse,
+    MerkleTreeInfoResponse, MigrateMsg, PauseTarget, PoolMsg, PoolsResponse, ProtocolFeeMsg,
+    ProtocolFeeRate, ProtocolFeeResponse, QueryMsg, RelayerResponse, RelayersResponse,
+    RootHistoryResponse, StatusResponse, VerifyingKeyResponse, WithdrawMsg,
+};
+use crate::state::{
+    config_read, get_contract_version, mixer_read, mixer_write, nullifier_read, read_root,
+    read_subtree, read_zero, set_contract_version, PoolFee, DEFAULT_ROOT_HISTORY_SIZE,
+    FILLED_SUBTREES_KEY, MAX_ROOT_HISTORY_SIZE, MAX_VK_UPDATE_DELAY, MERKLE_ROOTS_KEY,
+    USED_NULLIFIERS_KEY,
+};
 use crate::test_util::Element;
 use crate::test_util::MERKLE_TREE_LEVELS;
-use crate::utils::truncate_and_pad;
+use crate::utils::{
+    encode_address, encode_domain, encode_expiry, is_canonical, sha256, BLS381_MODULUS,
+    BN254_MODULUS,
+};
+use crate::zeroes::{zeroes, DEFAULT_LEAF};
 
 const DEPOSIT_SIZE: &str = "1000000";
 const NATIVE_TOKEN_DENOM: &str = "orai";
+const CW20_ADDRESS: &str = "orai1cw20token";
+const VK_UPDATE_DELAY: u64 = 86400;
 
 const RECIPIENT: &str = "orai1kejftqzx05y9rv00lw5m76csfmx7lf9se02dz4";
 const RELAYER: &str = "orai1jrj2vh6cstqwk3pg8nkmdf0r9z0n3q3f3jk5xn";
@@ -36,17 +62,44 @@ fn test() {
     )
 }
 
+/// Single native pool of `DEPOSIT_SIZE`, tests override the fields they exercise
+fn base_instantiate_msg() -> InstantiateMsg {
+    InstantiateMsg {
+        admin: None,
+        merkletree_levels: MERKLE_TREE_LEVELS as u32,
+        curve: crate::msg::Curve::Bn254,
+        zero_leaf: None,
+        vk_raw: Binary::from_base64(VK_RAW).unwrap(),
+        vk_update_delay: VK_UPDATE_DELAY,
+        excess_funds: None,
+        refund_denom: None,
+        root_history_size: None,
+        pools: vec![PoolMsg {
+            deposit_size: Uint128::try_from(DEPOSIT_SIZE).unwrap(),
+            asset: AssetInfoMsg::NativeToken {
+                denom: NATIVE_TOKEN_DENOM.to_string(),
+            },
+        }],
+    }
+}
+
 fn create_mixer() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+    create_mixer_with_asset(AssetInfo::NativeToken {
+        denom: NATIVE_TOKEN_DENOM.to_string(),
+    })
+}
+
+fn create_mixer_with_asset(asset: AssetInfo) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
     let mut deps = mock_dependencies();
     // Initialize the contract
     let env = mock_env();
     let info = mock_info("anyone", &[]);
     let instantiate_msg = InstantiateMsg {
-        merkletree_levels: MERKLE_TREE_LEVELS as u32,
-        deposit_size: Uint128::try_from(DEPOSIT_SIZE).unwrap(),
-        native_token_denom: NATIVE_TOKEN_DENOM.to_string(),
-        curve: 1,
-        vk_raw: Binary::from_base64(VK_RAW).unwrap(),
+        pools: vec![PoolMsg {
+            deposit_size: Uint128::try_from(DEPOSIT_SIZE).unwrap(),
+            asset: asset.into(),
+        }],
+        ..base_instantiate_msg()
     };
 
     let _ = instantiate(deps.as_mut(), env, info, instantiate_msg).unwrap();
@@ -61,8 +114,25 @@ fn prepare_zk_circuit(
     fee: u128,
     refund: u128,
 ) -> (Vec<u8>, Element, Element, Element) {
-    let recipient_bytes = RECIPIENT.as_bytes();
-    let relayer_bytes = relayer.as_bytes();
+    prepare_zk_circuit_until(index, curve, relayer, fee, refund, None)
+}
+
+fn prepare_zk_circuit_until(
+    index: u64,
+    curve: Curve,
+    relayer: &str,
+    fee: u128,
+    refund: u128,
+    valid_until_height: Option<u64>,
+) -> (Vec<u8>, Element, Element, Element) {
+    let recipient_bytes = encode_address(RECIPIENT);
+    let env = mock_env();
+    let relayer_bytes = [
+        encode_address(relayer),
+        encode_domain(&env.block.chain_id, env.contract.address.as_str()),
+        encode_expiry(valid_until_height),
+    ]
+    .concat();
     let fee_value = fee;
     let refund_value = refund;
 
@@ -70,8 +140,8 @@ fn prepare_zk_circuit(
     crate::test_util::setup_zk_circuit(
         index,
         curve,
-        truncate_and_pad(recipient_bytes),
-        truncate_and_pad(relayer_bytes),
+        recipient_bytes,
+        relayer_bytes,
         fee_value,
         refund_value,
     )
@@ -83,13 +153,7 @@ fn test_mixer_proper_initialization() {
 
     let env = mock_env();
     let info = mock_info("anyone", &[]);
-    let instantiate_msg = InstantiateMsg {
-        merkletree_levels: MERKLE_TREE_LEVELS as u32,
-        deposit_size: Uint128::try_from(DEPOSIT_SIZE).unwrap(),
-        native_token_denom: NATIVE_TOKEN_DENOM.to_string(),
-        curve: 1,
-        vk_raw: Binary::from_base64(VK_RAW).unwrap(),
-    };
+    let instantiate_msg = base_instantiate_msg();
 
     // Should pass this "unwrap" if success.
     let response = instantiate(deps.as_mut(), env, info, instantiate_msg).unwrap();
@@ -121,6 +185,7 @@ fn test_mixer_should_be_able_to_deposit_native_token() {
     // Try the deposit with insufficient fund
     let info = mock_info("depositor", &[Coin::new(1_000_u128, NATIVE_TOKEN_DENOM)]);
     let deposit_msg = DepositMsg {
+        pool_id: 0,
         commitment: element_bin.clone(),
     };
 
@@ -131,7 +196,7 @@ fn test_mixer_should_be_able_to_deposit_native_token() {
         ExecuteMsg::Deposit(deposit_msg),
     )
     .unwrap_err();
-    assert_eq!(err.to_string(), "Insufficient_funds".to_string());
+    assert_eq!(err.to_string(), "[2002] Insufficient_funds".to_string());
 
     // Try the deposit for success
     let info = mock_info(
@@ -139,6 +204,7 @@ fn test_mixer_should_be_able_to_deposit_native_token() {
         &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
     );
     let deposit_msg = DepositMsg {
+        pool_id: 0,
         commitment: element_bin,
     };
 
@@ -166,6 +232,7 @@ fn test_mixer_should_withdraw_native_token() {
     // Try the deposit for success
     let info = mock_info("anyone", &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)]);
     let deposit_msg = DepositMsg {
+        pool_id: 0,
         commitment: Binary::from(leaf_element.0.to_vec()),
     };
 
@@ -179,13 +246,66 @@ fn test_mixer_should_withdraw_native_token() {
     assert_eq!(response.events.len(), 1);
 
     let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
         proof_bytes: proof_bytes_bin,
         root: root_element_bin,
         nullifier_hash: nullifier_hash_bin,
         recipient: RECIPIENT.to_string(),
-        relayer: RELAYER.to_string(),
+        relayer: Some(RELAYER.to_string()),
+        fee: Uint128::from(FEE),
+        refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
+    };
+    let info = mock_info("withdraw", &[]);
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Withdraw(withdraw_msg),
+    )
+    .unwrap();
+    assert_eq!(response.events.len(), 1);
+}
+
+#[test]
+fn test_mixer_should_query_spent_nullifiers_and_known_roots() {
+    let mut deps = create_mixer();
+
+    let (proof_bytes, root_element, nullifier_hash_element, leaf_element) =
+        prepare_zk_circuit(0, Curve::Bn254, RELAYER, FEE, REFUND);
+
+    let proof_bytes_bin = Binary::from(proof_bytes);
+    let root_element_bin = Binary::from(root_element.0.to_vec());
+    let nullifier_hash_bin = Binary::from(nullifier_hash_element.0.to_vec());
+
+    // Try the deposit for success
+    let info = mock_info("anyone", &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)]);
+    let deposit_msg = DepositMsg {
+        pool_id: 0,
+        commitment: Binary::from(leaf_element.0.to_vec()),
+    };
+
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(deposit_msg.clone()),
+    )
+    .unwrap();
+    assert_eq!(response.events.len(), 1);
+
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: proof_bytes_bin,
+        root: root_element_bin.clone(),
+        nullifier_hash: nullifier_hash_bin.clone(),
+        recipient: RECIPIENT.to_string(),
+        relayer: Some(RELAYER.to_string()),
         fee: Uint128::from(FEE),
         refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
     };
     let info = mock_info("withdraw", &[]);
     let response = execute(
@@ -196,6 +316,49 @@ fn test_mixer_should_withdraw_native_token() {
     )
     .unwrap();
     assert_eq!(response.events.len(), 1);
+
+    let res: IsSpentResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::IsSpent {
+                pool_id: 0,
+                nullifier_hash: nullifier_hash_bin.clone(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(res.spent);
+
+    let res: AreSpentResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::AreSpent {
+                pool_id: 0,
+                nullifier_hashes: vec![nullifier_hash_bin, Binary::from([1u8; 32].to_vec())],
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.spent, vec![true, false]);
+
+    let res: IsKnownRootResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::IsKnownRoot {
+                pool_id: 0,
+                root: root_element_bin,
+                epoch: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(res.known);
 }
 
 #[test]
@@ -214,6 +377,7 @@ fn test_mixer_should_fail_when_invalid_merkle_roots() {
         &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
     );
     let deposit_msg = DepositMsg {
+        pool_id: 0,
         commitment: Binary::from(leaf_element.0.to_vec()),
     };
 
@@ -225,7 +389,7 @@ fn test_mixer_should_fail_when_invalid_merkle_roots() {
     )
     .unwrap();
     assert_eq!(response.events.len(), 1);
-    let on_chain_root = read_root(&deps.storage, 1);
+    let on_chain_root = read_root(&deps.storage, 0, 0, 1);
     let local_root = root_element.0;
     assert_eq!(on_chain_root, local_root);
 
@@ -234,13 +398,16 @@ fn test_mixer_should_fail_when_invalid_merkle_roots() {
     let root_element_bin = Binary::from(root_element.0.to_vec());
 
     let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
         proof_bytes: proof_bytes_bin,
         root: root_element_bin,
         nullifier_hash: nullifier_hash_bin,
         recipient: RECIPIENT.to_string(),
-        relayer: RELAYER.to_string(),
+        relayer: Some(RELAYER.to_string()),
         fee: Uint128::from(FEE),
         refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
     };
     let info = mock_info("withdraw", &[]);
     let err = execute(
@@ -250,8 +417,2392 @@ fn test_mixer_should_fail_when_invalid_merkle_roots() {
         ExecuteMsg::Withdraw(withdraw_msg),
     )
     .unwrap_err();
+    assert_eq!(err.to_string(), "[3008] Root is not known".to_string());
+}
+
+#[test]
+fn test_mixer_should_be_able_to_deposit_cw20_token() {
+    let mut deps = create_mixer_with_asset(AssetInfo::Token {
+        contract_addr: Addr::unchecked(CW20_ADDRESS),
+    });
+
+    let (_, _, _, leaf_element) = prepare_zk_circuit(0, Curve::Bn254, RELAYER, FEE, REFUND);
+    let hook_msg = to_binary(&Cw20HookMsg::Deposit(DepositMsg {
+        pool_id: 0,
+        commitment: Binary::from(leaf_element.0.to_vec()),
+    }))
+    .unwrap();
+
+    // Native deposits are rejected by cw20 pools
+    let info = mock_info(
+        "depositor",
+        &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+    );
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidAsset {});
+
+    // Only the pool token contract can call the hook
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("fake_token", &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "depositor".to_string(),
+            amount: Uint128::try_from(DEPOSIT_SIZE).unwrap(),
+            msg: hook_msg.clone(),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(CW20_ADDRESS, &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "depositor".to_string(),
+            amount: Uint128::try_from(DEPOSIT_SIZE).unwrap(),
+            msg: Binary::from(b"{}".to_vec()),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidHookMsg {});
+
+    // Try the deposit with insufficient fund
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(CW20_ADDRESS, &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "depositor".to_string(),
+            amount: Uint128::from(1_000u128),
+            msg: hook_msg.clone(),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InsufficientFunds {});
+
+    // Try the deposit for success
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(CW20_ADDRESS, &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "depositor".to_string(),
+            amount: Uint128::try_from(DEPOSIT_SIZE).unwrap(),
+            msg: hook_msg,
+        }),
+    )
+    .unwrap();
+    assert_eq!(response.events.len(), 1);
+}
+
+#[test]
+fn test_mixer_should_withdraw_cw20_token() {
+    let mut deps = create_mixer_with_asset(AssetInfo::Token {
+        contract_addr: Addr::unchecked(CW20_ADDRESS),
+    });
+
+    let (proof_bytes, root_element, nullifier_hash_element, leaf_element) =
+        prepare_zk_circuit(0, Curve::Bn254, RELAYER, FEE, REFUND);
+
+    let hook_msg = to_binary(&Cw20HookMsg::Deposit(DepositMsg {
+        pool_id: 0,
+        commitment: Binary::from(leaf_element.0.to_vec()),
+    }))
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(CW20_ADDRESS, &[]),
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "depositor".to_string(),
+            amount: Uint128::try_from(DEPOSIT_SIZE).unwrap(),
+            msg: hook_msg,
+        }),
+    )
+    .unwrap();
+
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::from(proof_bytes),
+        root: Binary::from(root_element.0.to_vec()),
+        nullifier_hash: Binary::from(nullifier_hash_element.0.to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: Some(RELAYER.to_string()),
+        fee: Uint128::from(FEE),
+        refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
+    };
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg),
+    )
+    .unwrap();
+
+    assert_eq!(response.messages.len(), 1);
+    assert_eq!(
+        response.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: CW20_ADDRESS.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: RECIPIENT.to_string(),
+                amount: Uint128::try_from(DEPOSIT_SIZE).unwrap(),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+}
+
+#[test]
+fn test_mixer_should_host_multiple_pools() {
+    let mut deps = mock_dependencies();
+    let instantiate_msg = InstantiateMsg {
+        pools: vec![
+            PoolMsg {
+                deposit_size: Uint128::from(1_000_000u128),
+                asset: AssetInfoMsg::NativeToken {
+                    denom: NATIVE_TOKEN_DENOM.to_string(),
+                },
+            },
+            PoolMsg {
+                deposit_size: Uint128::from(10_000_000u128),
+                asset: AssetInfoMsg::NativeToken {
+                    denom: NATIVE_TOKEN_DENOM.to_string(),
+                },
+            },
+        ],
+        ..base_instantiate_msg()
+    };
+
+    // Pools can not be added later, so at least one is required
+    let err = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        InstantiateMsg {
+            pools: vec![],
+            ..instantiate_msg.clone()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::NoPools {});
+
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        instantiate_msg,
+    )
+    .unwrap();
+
+    let (_, _, _, leaf_element) = prepare_zk_circuit(0, Curve::Bn254, RELAYER, FEE, REFUND);
+
+    // Deposit size of the second pool applies
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        ),
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 1,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InsufficientFunds {});
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "depositor",
+            &[Coin::new(10_000_000_u128, NATIVE_TOKEN_DENOM)],
+        ),
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 1,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap();
+
+    // Each pool keeps its own tree
+    let info: MerkleTreeInfoResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::MerkleTreeInfo { pool_id: 0 },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(info.next_index, 0);
+    assert_eq!(read_root(&deps.storage, 0, 0, 1), DEFAULT_LEAF);
+    assert_ne!(read_root(&deps.storage, 1, 0, 1), DEFAULT_LEAF);
+
+    let res: PoolsResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Pools {}).unwrap()).unwrap();
+    assert_eq!(res.pools.len(), 2);
+    assert_eq!(res.pools[0].pool_id, 0);
+    assert_eq!(res.pools[0].next_index, 0);
+    assert_eq!(res.pools[1].pool_id, 1);
+    assert_eq!(res.pools[1].deposit_size, "10000000");
+    assert_eq!(res.pools[1].next_index, 1);
+}
+
+#[test]
+fn test_mixer_admin_should_be_transferred_in_two_steps() {
+    let mut deps = create_mixer();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("someone", &[]),
+        ExecuteMsg::ProposeAdmin {
+            admin: "new_admin".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ProposeAdmin {
+            admin: "x".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidAddress {
+            address: "x".to_string()
+        }
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ProposeAdmin {
+            admin: "new_admin".to_string(),
+        },
+    )
+    .unwrap();
+
+    // Only the proposed admin can accept the role
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("someone", &[]),
+        ExecuteMsg::AcceptAdmin {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let status: StatusResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Status {}).unwrap()).unwrap();
+    assert_eq!(status.admin, "anyone");
+    assert_eq!(status.pending_admin, Some("new_admin".to_string()));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("new_admin", &[]),
+        ExecuteMsg::AcceptAdmin {},
+    )
+    .unwrap();
+
+    let status: StatusResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Status {}).unwrap()).unwrap();
+    assert_eq!(status.admin, "new_admin");
+    assert_eq!(status.pending_admin, None);
+}
+
+#[test]
+fn test_mixer_should_not_deposit_or_withdraw_when_paused() {
+    let mut deps = create_mixer();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("someone", &[]),
+        ExecuteMsg::Pause {
+            target: PauseTarget::All,
+            reason: "incident".to_string(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Pause {
+            target: PauseTarget::All,
+            reason: "incident".to_string(),
+        },
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        ),
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from([1u8; 32].to_vec()),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DepositsPaused {
+            reason: "incident".to_string()
+        }
+    );
+
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::default(),
+        root: Binary::from([1u8; 32].to_vec()),
+        nullifier_hash: Binary::from([1u8; 32].to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: Some(RELAYER.to_string()),
+        fee: Uint128::from(FEE),
+        refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg),
+    )
+    .unwrap_err();
     assert_eq!(
-        err.to_string(),
-        "Generic error: Root is not known".to_string()
+        err,
+        ContractError::WithdrawalsPaused {
+            reason: "incident".to_string()
+        }
     );
+
+    // Reopen deposits only
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::Unpause {
+            target: PauseTarget::Deposits,
+        },
+    )
+    .unwrap();
+
+    let status: StatusResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Status {}).unwrap()).unwrap();
+    assert!(!status.deposits_paused);
+    assert!(status.withdrawals_paused);
+    assert_eq!(status.reason, Some("incident".to_string()));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        ),
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from([1u8; 32].to_vec()),
+        }),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_mixer_should_migrate_from_v0_1() {
+    let mut deps = mock_dependencies();
+    let levels = MERKLE_TREE_LEVELS as u32;
+
+    // Lay out the storage the way 0.1.0 left it, its tree had no epoch nor history size
+    let legacy_mixer = format!(
+        r#"{{"deposit_size":"{}","native_token_denom":"{}","vk_raw":"{}","merkle_tree":{{"levels":{},"current_root_index":1,"next_index":1,"curve":1}}}}"#,
+        DEPOSIT_SIZE, NATIVE_TOKEN_DENOM, VK_RAW, levels
+    );
+    deps.storage.set(
+        &to_length_prefixed(LEGACY_MIXER_KEY),
+        legacy_mixer.as_bytes(),
+    );
+    for i in 0..levels {
+        prefixed(&mut deps.storage, FILLED_SUBTREES_KEY).set(&i.to_le_bytes(), &zeroes(i));
+    }
+    prefixed(&mut deps.storage, MERKLE_ROOTS_KEY).set(&0u32.to_le_bytes(), &zeroes(levels));
+    prefixed(&mut deps.storage, MERKLE_ROOTS_KEY).set(&1u32.to_le_bytes(), &[7u8; 32]);
+    prefixed(&mut deps.storage, USED_NULLIFIERS_KEY).set(&[9u8; 32], &[1u8]);
+
+    // 0.1.0 stored no admin
+    let err = migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            admin: None,
+            vk_update_delay: None,
+            excess_funds: None,
+            refund_denom: None,
+            root_history_size: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::MigrationAdminRequired {});
+
+    let response = migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            admin: Some("admin".to_string()),
+            vk_update_delay: None,
+            excess_funds: None,
+            refund_denom: None,
+            root_history_size: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        response.attributes,
+        vec![
+            attr("action", "migrate"),
+            attr("from_version", "0.1.0"),
+            attr("to_version", env!("CARGO_PKG_VERSION")),
+        ]
+    );
+
+    let version = get_contract_version(&deps.storage).unwrap().unwrap();
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(config_read(&deps.storage).unwrap().admin, "admin");
+
+    let mixer = mixer_read(&deps.storage, 0).unwrap();
+    assert_eq!(
+        mixer.asset,
+        AssetInfo::NativeToken {
+            denom: NATIVE_TOKEN_DENOM.to_string()
+        }
+    );
+    assert_eq!(mixer.merkle_tree.next_index, 1);
+    assert_eq!(mixer.merkle_tree.epoch, 0);
+    assert_eq!(
+        mixer.merkle_tree.root_history_size,
+        DEFAULT_ROOT_HISTORY_SIZE
+    );
+
+    // Prefixed stores are moved into the pool namespace
+    assert_eq!(read_root(&deps.storage, 0, 0, 0), zeroes(levels));
+    assert_eq!(read_root(&deps.storage, 0, 0, 1), [7u8; 32]);
+    assert!(mixer.merkle_tree.is_known_root(0, [7u8; 32], &deps.storage));
+    assert_eq!(read_subtree(&deps.storage, 0, 0, 3).unwrap(), zeroes(3));
+    // Spent nullifiers are left under the legacy prefix
+    assert!(nullifier_read(&deps.storage, 0, &[9u8; 32]));
+    assert!(prefixed_read(&deps.storage, USED_NULLIFIERS_KEY)
+        .get(&[9u8; 32])
+        .is_some());
+    assert!(prefixed_read(&deps.storage, MERKLE_ROOTS_KEY)
+        .get(&1u32.to_le_bytes())
+        .is_none());
+}
+
+#[test]
+fn test_mixer_should_not_migrate_from_other_contract() {
+    let mut deps = create_mixer();
+    assert_eq!(
+        get_contract_version(&deps.storage)
+            .unwrap()
+            .unwrap()
+            .version,
+        env!("CARGO_PKG_VERSION")
+    );
+
+    set_contract_version(&mut deps.storage, "crates.io:other", "1.0.0").unwrap();
+    let err = migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            admin: None,
+            vk_update_delay: None,
+            excess_funds: None,
+            refund_denom: None,
+            root_history_size: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::CannotMigrate {
+            previous_contract: "crates.io:other".to_string()
+        }
+    );
+
+    // Nor from a newer version of this contract
+    set_contract_version(&mut deps.storage, "crates.io:cosmwasm-mixer", "99.0.0").unwrap();
+    let err = migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            admin: None,
+            vk_update_delay: None,
+            excess_funds: None,
+            refund_denom: None,
+            root_history_size: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::CannotDowngrade {
+            stored_version: "99.0.0".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string()
+        }
+    );
+}
+
+#[test]
+fn test_mixer_should_bound_the_vk_update_delay() {
+    let mut deps = create_mixer();
+    let err = migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            admin: None,
+            vk_update_delay: Some(u64::MAX),
+            excess_funds: None,
+            refund_denom: None,
+            root_history_size: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidVkUpdateDelay {
+            delay: u64::MAX,
+            max: MAX_VK_UPDATE_DELAY
+        }
+    );
+}
+
+#[test]
+fn test_mixer_verifying_key_should_rotate_after_timelock() {
+    let mut deps = create_mixer();
+    let new_vk = Binary::from([7u8; 360].to_vec());
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("someone", &[]),
+        ExecuteMsg::ProposeVerifyingKey {
+            vk_raw: new_vk.clone(),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let env = mock_env();
+    let activates_at = env.block.time.seconds() + VK_UPDATE_DELAY;
+    let response = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ProposeVerifyingKey {
+            vk_raw: new_vk.clone(),
+        },
+    )
+    .unwrap();
+    assert_eq!(response.events[0].ty, "mixer-vk-update");
+
+    let vk: VerifyingKeyResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::VerifyingKey {}).unwrap()).unwrap();
+    assert_eq!(
+        vk.vk_hash,
+        Binary::from(sha256(&Binary::from_base64(VK_RAW).unwrap()))
+    );
+    assert_eq!(vk.pending_vk_hash, Some(Binary::from(sha256(&new_vk))));
+    assert_eq!(vk.activates_at, Some(activates_at));
+
+    // The pending key is timelocked
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ApplyVerifyingKey {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::VerifyingKeyTimelocked { activates_at });
+
+    // The admin can cancel during the delay
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::CancelVerifyingKey {},
+    )
+    .unwrap();
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ApplyVerifyingKey {},
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::NoPendingVerifyingKey {});
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::ProposeVerifyingKey {
+            vk_raw: new_vk.clone(),
+        },
+    )
+    .unwrap();
+
+    // Anyone can apply it once the delay has passed
+    let mut env = env;
+    env.block.time = env.block.time.plus_seconds(VK_UPDATE_DELAY);
+    execute(
+        deps.as_mut(),
+        env,
+        mock_info("someone", &[]),
+        ExecuteMsg::ApplyVerifyingKey {},
+    )
+    .unwrap();
+
+    let vk: VerifyingKeyResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::VerifyingKey {}).unwrap()).unwrap();
+    assert_eq!(vk.vk_hash, Binary::from(sha256(&new_vk)));
+    assert_eq!(vk.pending_vk_hash, None);
+    assert_eq!(config_read(&deps.storage).unwrap().vk_raw, new_vk);
+}
+
+#[test]
+fn test_mixer_should_store_commitments() {
+    let mut deps = create_mixer();
+
+    let commitments: Vec<Binary> = (1..=3u8).map(|i| Binary::from([i; 32].to_vec())).collect();
+    for commitment in commitments.iter() {
+        let info = mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        );
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Deposit(DepositMsg {
+                pool_id: 0,
+                commitment: commitment.clone(),
+            }),
+        )
+        .unwrap();
+    }
+
+    let res: CommitmentsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Commitments {
+                pool_id: 0,
+                start_after: None,
+                limit: Some(2),
+                epoch: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let page: Vec<(u32, Binary)> = res
+        .commitments
+        .into_iter()
+        .map(|c| (c.index, c.commitment))
+        .collect();
+    assert_eq!(
+        page,
+        vec![(0, commitments[0].clone()), (1, commitments[1].clone())]
+    );
+
+    let res: CommitmentsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Commitments {
+                pool_id: 0,
+                start_after: Some(1),
+                limit: None,
+                epoch: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.commitments.len(), 1);
+    assert_eq!(res.commitments[0].index, 2);
+    assert_eq!(res.commitments[0].commitment, commitments[2]);
+
+    let res: CommitmentIndexResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::CommitmentIndex {
+                pool_id: 0,
+                commitment: commitments[1].clone(),
+                epoch: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.index, Some(1));
+
+    let res: CommitmentIndexResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::CommitmentIndex {
+                pool_id: 0,
+                commitment: Binary::from([9u8; 32].to_vec()),
+                epoch: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.index, None);
+}
+
+#[test]
+fn test_mixer_should_serve_merkle_path() {
+    let mut deps = create_mixer();
+
+    let commitments: Vec<[u8; 32]> = (1..=3u8).map(|i| [i; 32]).collect();
+    for commitment in commitments.iter() {
+        let info = mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        );
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Deposit(DepositMsg {
+                pool_id: 0,
+                commitment: Binary::from(commitment.to_vec()),
+            }),
+        )
+        .unwrap();
+    }
+
+    let tree = mixer_read(&deps.storage, 0).unwrap().merkle_tree;
+    let current_root = read_root(&deps.storage, 0, 0, tree.current_root_index);
+
+    for index in 0..3u32 {
+        let res: MerklePathResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::MerklePath {
+                    pool_id: 0,
+                    index,
+                    epoch: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.path_elements.len(), MERKLE_TREE_LEVELS);
+        assert_eq!(res.root.as_slice(), current_root.as_slice());
+
+        // Hash the leaf up the path, it has to land on the root
+        let mut node = commitments[index as usize].to_vec();
+        let mut current_index = index;
+        for sibling in res.path_elements.iter() {
+            node = if current_index % 2 == 0 {
+                deps.api.poseidon_hash(&node, sibling, 1).unwrap()
+            } else {
+                deps.api.poseidon_hash(sibling, &node, 1).unwrap()
+            };
+            current_index /= 2;
+        }
+        assert_eq!(node, res.root.to_vec());
+    }
+
+    // Leaves not inserted yet have no path
+    assert!(query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::MerklePath {
+            pool_id: 0,
+            index: 3,
+            epoch: None
+        },
+    )
+    .is_err());
+}
+
+#[test]
+fn test_mixer_should_list_root_history() {
+    let mut deps = create_mixer();
+    let mut env = mock_env();
+    let created_height = env.block.height;
+
+    for i in 1..=3u8 {
+        env.block.height += 1;
+        let info = mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        );
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            info,
+            ExecuteMsg::Deposit(DepositMsg {
+                pool_id: 0,
+                commitment: Binary::from([i; 32].to_vec()),
+            }),
+        )
+        .unwrap();
+    }
+
+    let res: RootHistoryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::RootHistory {
+                pool_id: 0,
+                start_after: None,
+                limit: Some(2),
+                epoch: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let page: Vec<(u32, u32, Option<u64>)> = res
+        .roots
+        .iter()
+        .map(|r| (r.index, r.leaf_count, r.height))
+        .collect();
+    assert_eq!(
+        page,
+        vec![
+            (3, 3, Some(created_height + 3)),
+            (2, 2, Some(created_height + 2))
+        ]
+    );
+    assert_eq!(
+        res.roots[0].root.as_slice(),
+        read_root(&deps.storage, 0, 0, 3).as_slice()
+    );
+
+    // Continue down to the empty tree root written at instantiation
+    let res: RootHistoryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::RootHistory {
+                pool_id: 0,
+                start_after: Some(2),
+                limit: None,
+                epoch: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let page: Vec<(u32, u32, Option<u64>)> = res
+        .roots
+        .iter()
+        .map(|r| (r.index, r.leaf_count, r.height))
+        .collect();
+    assert_eq!(
+        page,
+        vec![
+            (1, 1, Some(created_height + 1)),
+            (0, 0, Some(created_height))
+        ]
+    );
+    assert_eq!(
+        res.roots[1].root.as_slice(),
+        read_zero(&deps.storage, 0, MERKLE_TREE_LEVELS as u32).as_slice()
+    );
+}
+
+#[test]
+fn test_mixer_should_batch_deposit() {
+    let mut deps = create_mixer();
+
+    let commitments: Vec<Binary> = (1..=3u8).map(|i| Binary::from([i; 32].to_vec())).collect();
+    let batch_msg = BatchDepositMsg {
+        pool_id: 0,
+        commitments: commitments.clone(),
+    };
+
+    // Fail when the funds do not match the batch size
+    let info = mock_info(
+        "depositor",
+        &[Coin::new(2_000_000_u128, NATIVE_TOKEN_DENOM)],
+    );
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::BatchDeposit(batch_msg.clone()),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InsufficientFunds {});
+
+    let info = mock_info("depositor", &[]);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::BatchDeposit(BatchDepositMsg {
+            pool_id: 0,
+            commitments: vec![],
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::EmptyBatch {});
+
+    let info = mock_info(
+        "depositor",
+        &[Coin::new(3_000_000_u128, NATIVE_TOKEN_DENOM)],
+    );
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::BatchDeposit(batch_msg),
+    )
+    .unwrap();
+    assert_eq!(response.events.len(), 1);
+    assert!(response.events[0]
+        .attributes
+        .contains(&attr("inserted_indices", "0,1,2")));
+    assert!(response.events[0]
+        .attributes
+        .contains(&attr("epochs", "0,0,0")));
+
+    let mixer = mixer_read(&deps.storage, 0).unwrap();
+    assert_eq!(mixer.merkle_tree.next_index, 3);
+    assert_eq!(mixer.merkle_tree.current_root_index, 3);
+}
+
+fn create_mixer_with_excess_funds(
+    asset: AssetInfo,
+    excess_funds: ExcessFunds,
+) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+    let mut deps = create_mixer_with_asset(asset);
+    migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            admin: None,
+            vk_update_delay: None,
+            excess_funds: Some(excess_funds),
+            refund_denom: None,
+            root_history_size: None,
+        },
+    )
+    .unwrap();
+    deps
+}
+
+#[test]
+fn test_mixer_should_reject_excess_funds() {
+    let mut deps = create_mixer();
+    let deposit_msg = DepositMsg {
+        pool_id: 0,
+        commitment: Binary::from([1u8; 32].to_vec()),
+    };
+
+    // Overpaying the deposit
+    let info = mock_info(
+        "depositor",
+        &[Coin::new(1_500_000_u128, NATIVE_TOKEN_DENOM)],
+    );
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(deposit_msg.clone()),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidDepositAmount {
+            expected: Uint128::from(1_000_000_u128),
+            received: Uint128::from(1_500_000_u128),
+        }
+    );
+
+    // Sending another denom along
+    let info = mock_info(
+        "depositor",
+        &[
+            Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM),
+            Coin::new(10_u128, "uatom"),
+        ],
+    );
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(deposit_msg.clone()),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::UnexpectedDenom {
+            denom: "uatom".to_string()
+        }
+    );
+
+    // The exact amount goes through without refund
+    let info = mock_info(
+        "depositor",
+        &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+    );
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(deposit_msg),
+    )
+    .unwrap();
+    assert!(response.messages.is_empty());
+}
+
+#[test]
+fn test_mixer_should_refund_excess_funds() {
+    let mut deps = create_mixer_with_excess_funds(
+        AssetInfo::NativeToken {
+            denom: NATIVE_TOKEN_DENOM.to_string(),
+        },
+        ExcessFunds::Refund,
+    );
+
+    let res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config { pool_id: 0 }).unwrap())
+            .unwrap();
+    assert_eq!(res.excess_funds, ExcessFunds::Refund);
+
+    // Still not less than the deposit size
+    let info = mock_info("depositor", &[Coin::new(999_999_u128, NATIVE_TOKEN_DENOM)]);
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from([1u8; 32].to_vec()),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InsufficientFunds {});
+
+    let info = mock_info(
+        "depositor",
+        &[
+            Coin::new(1_500_000_u128, NATIVE_TOKEN_DENOM),
+            Coin::new(10_u128, "uatom"),
+        ],
+    );
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from([1u8; 32].to_vec()),
+        }),
+    )
+    .unwrap();
+    assert_eq!(response.messages.len(), 1);
+    assert_eq!(
+        response.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "depositor".to_string(),
+            amount: vec![
+                Coin::new(500_000_u128, NATIVE_TOKEN_DENOM),
+                Coin::new(10_u128, "uatom"),
+            ],
+        })
+    );
+
+    // Batches refund the excess over all of their deposits
+    let info = mock_info(
+        "depositor",
+        &[Coin::new(2_000_001_u128, NATIVE_TOKEN_DENOM)],
+    );
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::BatchDeposit(BatchDepositMsg {
+            pool_id: 0,
+            commitments: vec![
+                Binary::from([2u8; 32].to_vec()),
+                Binary::from([3u8; 32].to_vec()),
+            ],
+        }),
+    )
+    .unwrap();
+    assert_eq!(
+        response.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "depositor".to_string(),
+            amount: vec![Coin::new(1_u128, NATIVE_TOKEN_DENOM)],
+        })
+    );
+}
+
+#[test]
+fn test_mixer_should_refund_excess_cw20_token() {
+    let asset = AssetInfo::Token {
+        contract_addr: Addr::unchecked(CW20_ADDRESS),
+    };
+
+    // Rejected by default
+    let mut deps = create_mixer_with_asset(asset.clone());
+    let receive_msg = Cw20ReceiveMsg {
+        sender: "depositor".to_string(),
+        amount: Uint128::from(1_200_000_u128),
+        msg: to_binary(&Cw20HookMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from([1u8; 32].to_vec()),
+        }))
+        .unwrap(),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(CW20_ADDRESS, &[]),
+        ExecuteMsg::Receive(receive_msg.clone()),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidDepositAmount {
+            expected: Uint128::from(1_000_000_u128),
+            received: Uint128::from(1_200_000_u128),
+        }
+    );
+
+    let mut deps = create_mixer_with_excess_funds(asset, ExcessFunds::Refund);
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(CW20_ADDRESS, &[]),
+        ExecuteMsg::Receive(receive_msg),
+    )
+    .unwrap();
+    assert_eq!(
+        response.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: CW20_ADDRESS.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "depositor".to_string(),
+                amount: Uint128::from(200_000_u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+}
+
+#[test]
+fn test_encode_address_should_bind_the_full_address() {
+    // Same first 20 bytes used to map to the same public input
+    let a = encode_address("orai1kejftqzx05y9rv00lw5m76csfmx7lf9se02dz4");
+    let b = encode_address("orai1kejftqzx05y9rv00aaaaaaaaaaaaaaaaaaaaaa");
+    assert_ne!(a, b);
+
+    // Short strings no longer panic and carry their length
+    let short = encode_address("abc");
+    assert_eq!(short, vec![1, 3, 0, 0, 0, b'a', b'b', b'c']);
+}
+
+#[test]
+fn test_mixer_should_validate_withdraw_addresses() {
+    let mut deps = create_mixer();
+
+    let (proof_bytes, root_element, nullifier_hash_element, leaf_element) =
+        prepare_zk_circuit(0, Curve::Bn254, "", FEE, REFUND);
+
+    let info = mock_info(
+        "depositor",
+        &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap();
+
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::from(proof_bytes),
+        root: Binary::from(root_element.0.to_vec()),
+        nullifier_hash: Binary::from(nullifier_hash_element.0.to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: None,
+        fee: Uint128::from(FEE),
+        refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
+    };
+
+    // Malformed addresses are rejected before the proof is checked
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(WithdrawMsg {
+            recipient: "x".to_string(),
+            ..withdraw_msg.clone()
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidRecipient {
+            address: "x".to_string()
+        }
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(WithdrawMsg {
+            relayer: Some("R".to_string()),
+            ..withdraw_msg.clone()
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidRelayer {
+            address: "R".to_string()
+        }
+    );
+
+    // A fee needs someone to pay it to
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(WithdrawMsg {
+            fee: Uint128::from(1_u128),
+            ..withdraw_msg.clone()
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::RelayerRequired {});
+
+    // No relayer at all without a fee
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg),
+    )
+    .unwrap();
+    assert_eq!(response.messages.len(), 1);
+}
+
+#[test]
+fn test_mixer_should_not_withdraw_on_another_deployment() {
+    let mut deps = create_mixer();
+
+    let (proof_bytes, root_element, nullifier_hash_element, leaf_element) =
+        prepare_zk_circuit(0, Curve::Bn254, RELAYER, FEE, REFUND);
+
+    let info = mock_info(
+        "depositor",
+        &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap();
+
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::from(proof_bytes),
+        root: Binary::from(root_element.0.to_vec()),
+        nullifier_hash: Binary::from(nullifier_hash_element.0.to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: Some(RELAYER.to_string()),
+        fee: Uint128::from(FEE),
+        refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
+    };
+
+    // Same code and state, but another chain
+    let mut env = mock_env();
+    env.block.chain_id = "other-chain".to_string();
+    let err = execute(
+        deps.as_mut(),
+        env,
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg.clone()),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidWithdrawProof);
+
+    // Same chain, but another contract
+    let mut env = mock_env();
+    env.contract.address = Addr::unchecked("other_contract");
+    let err = execute(
+        deps.as_mut(),
+        env,
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg.clone()),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidWithdrawProof);
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_mixer_should_not_withdraw_after_proof_expiry() {
+    let mut deps = create_mixer();
+    let valid_until_height = mock_env().block.height + 10;
+
+    let (proof_bytes, root_element, nullifier_hash_element, leaf_element) =
+        prepare_zk_circuit_until(
+            0,
+            Curve::Bn254,
+            RELAYER,
+            FEE,
+            REFUND,
+            Some(valid_until_height),
+        );
+
+    let info = mock_info(
+        "depositor",
+        &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap();
+
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::from(proof_bytes),
+        root: Binary::from(root_element.0.to_vec()),
+        nullifier_hash: Binary::from(nullifier_hash_element.0.to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: Some(RELAYER.to_string()),
+        fee: Uint128::from(FEE),
+        refund: Uint128::from(REFUND),
+        valid_until_height: Some(valid_until_height),
+        epoch: None,
+    };
+
+    let mut env = mock_env();
+    env.block.height = valid_until_height + 1;
+    let err = execute(
+        deps.as_mut(),
+        env,
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg.clone()),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::ProofExpired { valid_until_height });
+
+    // The expiry is bound into the proof, it can not be stripped or extended
+    let mut env = mock_env();
+    env.block.height = valid_until_height;
+    for other in [None, Some(valid_until_height + 100)] {
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("withdraw", &[]),
+            ExecuteMsg::Withdraw(WithdrawMsg {
+                valid_until_height: other,
+                ..withdraw_msg.clone()
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidWithdrawProof);
+    }
+
+    execute(
+        deps.as_mut(),
+        env,
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_mixer_should_roll_over_into_a_new_epoch() {
+    let mut deps = mock_dependencies();
+    let instantiate_msg = InstantiateMsg {
+        merkletree_levels: 2,
+        ..base_instantiate_msg()
+    };
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        instantiate_msg,
+    )
+    .unwrap();
+
+    let deposit = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, i: u8| {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(
+                "depositor",
+                &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+            ),
+            ExecuteMsg::Deposit(DepositMsg {
+                pool_id: 0,
+                commitment: Binary::from([i; 32].to_vec()),
+            }),
+        )
+        .unwrap()
+    };
+
+    // Fill the 4 leaves of the first tree
+    for i in 1..=4u8 {
+        deposit(&mut deps, i);
+    }
+    let tree = mixer_read(&deps.storage, 0).unwrap().merkle_tree;
+    let full_root = read_root(&deps.storage, 0, 0, tree.current_root_index);
+
+    // The next deposit goes to the first leaf of a new tree
+    let response = deposit(&mut deps, 5);
+    assert!(response.events[0].attributes.contains(&attr("epoch", "1")));
+    assert!(response.events[0]
+        .attributes
+        .contains(&attr("inserted_index", "0")));
+
+    let info: MerkleTreeInfoResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::MerkleTreeInfo { pool_id: 0 },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(info.epoch, 1);
+    assert_eq!(info.next_index, 1);
+
+    // The last root of the full tree is known in its own epoch only
+    for (epoch, known) in [(None, false), (Some(0), true)] {
+        let res: IsKnownRootResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::IsKnownRoot {
+                    pool_id: 0,
+                    root: Binary::from(full_root.to_vec()),
+                    epoch,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.known, known);
+    }
+
+    let res: RootHistoryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::RootHistory {
+                pool_id: 0,
+                start_after: None,
+                limit: Some(1),
+                epoch: Some(0),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.epoch, 0);
+    assert_eq!(res.roots[0].leaf_count, 4);
+    assert_eq!(res.roots[0].root.as_slice(), full_root.as_slice());
+
+    // Withdrawals against the old root get past the root check
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::from([0u8; 32].to_vec()),
+        root: Binary::from(full_root.to_vec()),
+        nullifier_hash: Binary::from([1u8; 32].to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: None,
+        fee: Uint128::zero(),
+        refund: Uint128::zero(),
+        valid_until_height: None,
+        epoch: Some(0),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg.clone()),
+    )
+    .unwrap_err();
+    assert_ne!(err, ContractError::UnknownRoot);
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(WithdrawMsg {
+            epoch: None,
+            ..withdraw_msg.clone()
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::UnknownRoot);
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(WithdrawMsg {
+            epoch: Some(7),
+            ..withdraw_msg
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::UnknownEpoch { epoch: 7 });
+}
+
+#[test]
+fn test_mixer_should_resize_root_history() {
+    let mut deps = mock_dependencies();
+    let instantiate_msg = InstantiateMsg {
+        root_history_size: Some(0),
+        ..base_instantiate_msg()
+    };
+    let err = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        instantiate_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidRootHistorySize {
+            size: 0,
+            max: MAX_ROOT_HISTORY_SIZE
+        }
+    );
+
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        InstantiateMsg {
+            root_history_size: Some(3),
+            ..instantiate_msg
+        },
+    )
+    .unwrap();
+    let config: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config { pool_id: 0 }).unwrap())
+            .unwrap();
+    assert_eq!(config.root_history_size, 3);
+
+    let deposit = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, i: u8| {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(
+                "depositor",
+                &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+            ),
+            ExecuteMsg::Deposit(DepositMsg {
+                pool_id: 0,
+                commitment: Binary::from([i; 32].to_vec()),
+            }),
+        )
+        .unwrap();
+        let tree = mixer_read(&deps.storage, 0).unwrap().merkle_tree;
+        read_root(&deps.storage, 0, 0, tree.current_root_index)
+    };
+    let is_known = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, root: [u8; 32]| {
+        mixer_read(&deps.storage, 0)
+            .unwrap()
+            .merkle_tree
+            .is_known_root(0, root, &deps.storage)
+    };
+    let resize = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, size: u32| {
+        migrate(
+            deps.as_mut(),
+            mock_env(),
+            MigrateMsg {
+                admin: None,
+                vk_update_delay: None,
+                excess_funds: None,
+                refund_denom: None,
+                root_history_size: Some(size),
+            },
+        )
+        .unwrap();
+    };
+
+    // The 4th deposit evicts the root of the 1st one
+    let roots: Vec<[u8; 32]> = (1..=4u8).map(|i| deposit(&mut deps, i)).collect();
+    assert!(!is_known(&deps, roots[0]));
+    assert!(roots[1..].iter().all(|root| is_known(&deps, *root)));
+
+    // Growing keeps the remaining roots, which now survive more deposits
+    resize(&mut deps, 5);
+    let tree = mixer_read(&deps.storage, 0).unwrap().merkle_tree;
+    assert_eq!(tree.current_root_index, 2);
+    let res: RootHistoryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::RootHistory {
+                pool_id: 0,
+                start_after: None,
+                limit: None,
+                epoch: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let leaf_counts: Vec<u32> = res.roots.iter().map(|r| r.leaf_count).collect();
+    assert_eq!(leaf_counts, vec![4, 3, 2]);
+
+    let newest = deposit(&mut deps, 5);
+    assert!(roots[1..].iter().all(|root| is_known(&deps, *root)));
+
+    // Shrinking drops the oldest roots
+    resize(&mut deps, 2);
+    assert!(is_known(&deps, newest));
+    assert!(is_known(&deps, roots[3]));
+    assert!(!is_known(&deps, roots[2]));
+
+    let root = deposit(&mut deps, 6);
+    assert!(is_known(&deps, root));
+    assert!(is_known(&deps, newest));
+    assert!(!is_known(&deps, roots[3]));
+}
+
+#[test]
+fn test_mixer_should_hash_zeroes_from_the_zero_leaf() {
+    let mut deps = mock_dependencies();
+    let zero_leaf = [42u8; 32];
+    let instantiate_msg = InstantiateMsg {
+        merkletree_levels: 31,
+        zero_leaf: Some(Binary::from(zero_leaf.to_vec())),
+        ..base_instantiate_msg()
+    };
+
+    for (msg, expected) in [
+        (
+            InstantiateMsg {
+                merkletree_levels: 32,
+                ..instantiate_msg.clone()
+            },
+            ContractError::InvalidMerkleTreeLevels {
+                levels: 32,
+                max: 31,
+            },
+        ),
+        (
+            InstantiateMsg {
+                zero_leaf: Some(Binary::from([42u8; 31].to_vec())),
+                ..instantiate_msg.clone()
+            },
+            ContractError::InvalidZeroLeaf {},
+        ),
+        (
+            InstantiateMsg {
+                zero_leaf: Some(Binary::from(BN254_MODULUS.to_vec())),
+                ..instantiate_msg.clone()
+            },
+            ContractError::InvalidZeroLeaf {},
+        ),
+    ] {
+        let err =
+            instantiate(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg).unwrap_err();
+        assert_eq!(err, expected);
+    }
+
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        instantiate_msg,
+    )
+    .unwrap();
+    let config: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config { pool_id: 0 }).unwrap())
+            .unwrap();
+    assert_eq!(config.zero_leaf.as_slice(), zero_leaf.as_slice());
+
+    // The empty root is the zero leaf hashed up all 31 levels
+    let mut zero = zero_leaf;
+    for _ in 0..31 {
+        let hash = deps.api.poseidon_hash(&zero, &zero, 1).unwrap();
+        zero.copy_from_slice(&hash);
+    }
+    assert_eq!(read_root(&deps.storage, 0, 0, 0), zero);
+    assert_ne!(zero, zeroes(31));
+}
+
+#[test]
+fn test_mixer_should_withdraw_from_a_bls381_pool() {
+    let mut deps = mock_dependencies();
+    let (_, vk_raw) = crate::test_util::bls381_keys();
+    let instantiate_msg = InstantiateMsg {
+        curve: crate::msg::Curve::Bls381,
+        vk_raw: Binary::from(vk_raw.as_slice()),
+        ..base_instantiate_msg()
+    };
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        instantiate_msg,
+    )
+    .unwrap();
+
+    let config: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config { pool_id: 0 }).unwrap())
+            .unwrap();
+    assert_eq!(config.curve, crate::msg::Curve::Bls381);
+
+    let (proof_bytes, root_element, nullifier_hash_element, leaf_element) =
+        prepare_zk_circuit(0, Curve::Bls381, RELAYER, FEE, REFUND);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)]),
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap();
+
+    // The root is hashed on the pool curve, so the BLS12-381 proof matches it
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::from(proof_bytes),
+        root: Binary::from(root_element.0.to_vec()),
+        nullifier_hash: Binary::from(nullifier_hash_element.0.to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: Some(RELAYER.to_string()),
+        fee: Uint128::from(FEE),
+        refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
+    };
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("withdraw", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg),
+    )
+    .unwrap();
+    assert_eq!(response.events.len(), 1);
+}
+
+#[test]
+fn test_mixer_should_reject_invalid_commitments() {
+    let mut deps = create_mixer();
+    let mut deposit = |commitment: Vec<u8>| {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(
+                "depositor",
+                &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+            ),
+            ExecuteMsg::Deposit(DepositMsg {
+                pool_id: 0,
+                commitment: Binary::from(commitment),
+            }),
+        )
+    };
+
+    assert_eq!(
+        deposit([1u8; 31].to_vec()).unwrap_err(),
+        ContractError::InvalidCommitmentLength { length: 31 }
+    );
+    assert_eq!(
+        deposit([1u8; 33].to_vec()).unwrap_err(),
+        ContractError::InvalidCommitmentLength { length: 33 }
+    );
+    assert_eq!(
+        deposit(BN254_MODULUS.to_vec()).unwrap_err(),
+        ContractError::NonCanonicalCommitment {}
+    );
+
+    // The largest field element is fine, but only once
+    let mut max_element = BN254_MODULUS;
+    max_element[0] -= 1;
+    deposit(max_element.to_vec()).unwrap();
+    assert_eq!(
+        deposit(max_element.to_vec()).unwrap_err(),
+        ContractError::DuplicateCommitment {}
+    );
+
+    // A batch can not repeat a commitment either
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "depositor",
+            &[Coin::new(2_000_000_u128, NATIVE_TOKEN_DENOM)],
+        ),
+        ExecuteMsg::BatchDeposit(BatchDepositMsg {
+            pool_id: 0,
+            commitments: vec![Binary::from([2u8; 32].to_vec()); 2],
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::DuplicateCommitment {});
+
+    assert!(!is_canonical(&[0xff; 32], crate::msg::Curve::Bn254));
+    assert!(is_canonical(&BN254_MODULUS, crate::msg::Curve::Bls381));
+    assert!(!is_canonical(&BLS381_MODULUS, crate::msg::Curve::Bls381));
+}
+
+#[test]
+fn test_contract_errors_should_show_their_code() {
+    assert_eq!(ContractError::UnknownRoot.code(), 3008);
+    assert_eq!(
+        ContractError::AlreadyRevealedNullfier.to_string(),
+        "[3009] Nullifier is known"
+    );
+    assert_eq!(
+        ContractError::InvalidWithdrawProof.to_string(),
+        "[3010] Invalid withdraw proof"
+    );
+    assert_eq!(
+        ContractError::ProofExpired {
+            valid_until_height: 7
+        }
+        .to_string(),
+        "[3005] Withdraw proof expired at height 7"
+    );
+    assert_eq!(
+        ContractError::from(StdError::generic_err("oops")).to_string(),
+        "[1000] Generic error: oops"
+    );
+}
+
+#[test]
+fn test_mixer_should_enforce_the_refund_denom() {
+    let mut deps = create_mixer();
+    let refund = 5_000u128;
+    let (proof_bytes, root_element, nullifier_hash_element, leaf_element) =
+        prepare_zk_circuit(0, Curve::Bn254, RELAYER, FEE, refund);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        ),
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap();
+
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::from(proof_bytes),
+        root: Binary::from(root_element.0.to_vec()),
+        nullifier_hash: Binary::from(nullifier_hash_element.0.to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: Some(RELAYER.to_string()),
+        fee: Uint128::from(FEE),
+        refund: Uint128::from(refund),
+        valid_until_height: None,
+        epoch: None,
+    };
+    let mut withdraw = |msg: WithdrawMsg, funds: &[Coin]| {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("relayer", funds),
+            ExecuteMsg::Withdraw(msg),
+        )
+    };
+
+    let err = withdraw(withdraw_msg.clone(), &[]).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::MissingRefund {
+            refund: Uint128::from(refund)
+        }
+    );
+    let err = withdraw(withdraw_msg.clone(), &[Coin::new(refund, "uatom")]).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::RefundDenomMismatch {
+            expected: NATIVE_TOKEN_DENOM.to_string(),
+            denom: "uatom".to_string()
+        }
+    );
+    let err = withdraw(
+        withdraw_msg.clone(),
+        &[Coin::new(refund - 1, NATIVE_TOKEN_DENOM)],
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::RefundAmountMismatch {
+            expected: Uint128::from(refund),
+            received: Uint128::from(refund - 1)
+        }
+    );
+    let err = withdraw(
+        WithdrawMsg {
+            refund: Uint128::zero(),
+            ..withdraw_msg.clone()
+        },
+        &[Coin::new(refund, NATIVE_TOKEN_DENOM)],
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::UnexpectedRefund {});
+
+    // Only the refund is forwarded to the recipient
+    let response = withdraw(withdraw_msg, &[Coin::new(refund, NATIVE_TOKEN_DENOM)]).unwrap();
+    assert!(response.messages.iter().any(|msg| msg.msg
+        == CosmosMsg::Bank(BankMsg::Send {
+            to_address: RECIPIENT.to_string(),
+            amount: vec![Coin::new(refund, NATIVE_TOKEN_DENOM)],
+        })));
+    assert!(response.events[0]
+        .attributes
+        .contains(&attr("refund", "5000orai")));
+}
+
+#[test]
+fn test_mixer_relayer_registry() {
+    let mut deps = create_mixer();
+    let fee = 10_000u128;
+    let (proof_bytes, root_element, nullifier_hash_element, leaf_element) =
+        prepare_zk_circuit(0, Curve::Bn254, RELAYER, fee, REFUND);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        ),
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap();
+
+    let update_policy = ExecuteMsg::UpdateRelayerPolicy {
+        registration_required: Some(true),
+        max_fee_bps: Some(200),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("intruder", &[]),
+        update_policy.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        update_policy,
+    )
+    .unwrap();
+
+    let register = |fee_bps: u16| ExecuteMsg::RegisterRelayer {
+        fee_bps,
+        metadata_url: "https://relayer.example".to_string(),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(RELAYER, &[]),
+        register(300),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FeeBpsTooHigh {
+            fee_bps: 300,
+            max: 200
+        }
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(RELAYER, &[]),
+        register(50),
+    )
+    .unwrap();
+
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::from(proof_bytes),
+        root: Binary::from(root_element.0.to_vec()),
+        nullifier_hash: Binary::from(nullifier_hash_element.0.to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: Some(RELAYER.to_string()),
+        fee: Uint128::from(fee),
+        refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        ExecuteMsg::Withdraw(WithdrawMsg {
+            relayer: Some("unregistered".to_string()),
+            ..withdraw_msg.clone()
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::UnregisteredRelayer {
+            address: "unregistered".to_string()
+        }
+    );
+    // 1% of the deposit size is above the declared 0.5%
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg.clone()),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::RelayerFeeTooHigh {
+            fee: Uint128::from(fee),
+            max: Uint128::from(5_000u128)
+        }
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(RELAYER, &[]),
+        register(100),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg),
+    )
+    .unwrap();
+
+    let relayers: RelayersResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Relayers {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        relayers.relayers,
+        vec![RelayerResponse {
+            address: RELAYER.to_string(),
+            fee_bps: Some(100),
+            metadata_url: Some("https://relayer.example".to_string()),
+            withdrawals: 1,
+            fees_earned: vec![PoolFee {
+                pool_id: 0,
+                amount: Uint128::from(fee)
+            }],
+        }]
+    );
+
+    // Stats outlive the registration
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(RELAYER, &[]),
+        ExecuteMsg::UnregisterRelayer {},
+    )
+    .unwrap();
+    let relayer: RelayerResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Relayer {
+                address: RELAYER.to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(relayer.fee_bps, None);
+    assert_eq!(relayer.withdrawals, 1);
+    let relayers: RelayersResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Relayers {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(relayers.relayers.is_empty());
+}
+
+#[test]
+fn test_mixer_should_pay_the_protocol_fee() {
+    let mut deps = create_mixer();
+    let (proof_bytes, root_element, nullifier_hash_element, leaf_element) =
+        prepare_zk_circuit(0, Curve::Bn254, RELAYER, FEE, REFUND);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        ),
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 0,
+            commitment: Binary::from(leaf_element.0.to_vec()),
+        }),
+    )
+    .unwrap();
+
+    let update_fee = |rate: ProtocolFeeRate| ExecuteMsg::UpdateProtocolFee {
+        protocol_fee: Some(ProtocolFeeMsg {
+            rate,
+            treasury: "treasury".to_string(),
+        }),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("intruder", &[]),
+        update_fee(ProtocolFeeRate::Bps(100)),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        update_fee(ProtocolFeeRate::Flat(Uint128::from(60_000u128))),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::ProtocolFeeTooHigh {
+            pool_id: 0,
+            fee: Uint128::from(60_000u128),
+            max: Uint128::from(50_000u128)
+        }
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        update_fee(ProtocolFeeRate::Bps(100)),
+    )
+    .unwrap();
+
+    let config: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config { pool_id: 0 }).unwrap())
+            .unwrap();
+    assert_eq!(
+        config.protocol_fee,
+        Some(ProtocolFeeResponse {
+            rate: ProtocolFeeRate::Bps(100),
+            treasury: "treasury".to_string(),
+            amount: Uint128::from(10_000u128),
+        })
+    );
+
+    let withdraw_msg = WithdrawMsg {
+        pool_id: 0,
+        proof_bytes: Binary::from(proof_bytes),
+        root: Binary::from(root_element.0.to_vec()),
+        nullifier_hash: Binary::from(nullifier_hash_element.0.to_vec()),
+        recipient: RECIPIENT.to_string(),
+        relayer: Some(RELAYER.to_string()),
+        fee: Uint128::from(FEE),
+        refund: Uint128::from(REFUND),
+        valid_until_height: None,
+        epoch: None,
+    };
+    // The fees are checked against the deposit size before the proof
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        ExecuteMsg::Withdraw(WithdrawMsg {
+            fee: Uint128::from(995_000u128),
+            ..withdraw_msg.clone()
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FeeExceedsDepositSize {
+            fee: Uint128::from(1_005_000u128),
+            deposit_size: Uint128::from(1_000_000u128)
+        }
+    );
+
+    let response = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        ExecuteMsg::Withdraw(withdraw_msg),
+    )
+    .unwrap();
+    let sent: Vec<_> = response
+        .messages
+        .iter()
+        .map(|msg| msg.msg.clone())
+        .collect();
+    assert_eq!(
+        sent,
+        vec![
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: RECIPIENT.to_string(),
+                amount: vec![Coin::new(990_000u128, NATIVE_TOKEN_DENOM)],
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "treasury".to_string(),
+                amount: vec![Coin::new(10_000u128, NATIVE_TOKEN_DENOM)],
+            }),
+        ]
+    );
+    let attributes = &response.events[0].attributes;
+    assert!(attributes.contains(&attr("protocol_fee", "10000")));
+    assert!(attributes.contains(&attr("treasury", "treasury")));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("anyone", &[]),
+        ExecuteMsg::UpdateProtocolFee { protocol_fee: None },
+    )
+    .unwrap();
+    let config: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config { pool_id: 0 }).unwrap())
+            .unwrap();
+    assert_eq!(config.protocol_fee, None);
+}
+
+#[test]
+fn test_mixer_should_report_unknown_pools_and_oversized_batches() {
+    let mut deps = create_mixer();
+    let commitment = Binary::from([1u8; 32].to_vec());
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "depositor",
+            &[Coin::new(1_000_000_u128, NATIVE_TOKEN_DENOM)],
+        ),
+        ExecuteMsg::Deposit(DepositMsg {
+            pool_id: 7,
+            commitment: commitment.clone(),
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::UnknownPool { pool_id: 7 });
+
+    let mut mixer = mixer_read(&deps.storage, 0).unwrap();
+    mixer.deposit_size = Uint128::MAX;
+    mixer_write(&mut deps.storage, 0, &mixer).unwrap();
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("depositor", &[]),
+        ExecuteMsg::BatchDeposit(BatchDepositMsg {
+            pool_id: 0,
+            commitments: vec![commitment.clone(), commitment],
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::BatchTooLarge { count: 2 });
 }
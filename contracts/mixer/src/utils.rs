@@ -1,3 +1,19 @@
+use sha2::{Digest, Sha256};
+
+use crate::msg::Curve;
+
+/// Scalar field modulus of BN254, little endian
+pub const BN254_MODULUS: [u8; 32] = [
+    1, 0, 0, 240, 147, 245, 225, 67, 145, 112, 185, 121, 72, 232, 51, 40, 93, 88, 129, 129, 182,
+    69, 80, 184, 41, 160, 49, 225, 114, 78, 100, 48,
+];
+
+/// Scalar field modulus of BLS12-381, little endian
+pub const BLS381_MODULUS: [u8; 32] = [
+    1, 0, 0, 0, 255, 255, 255, 255, 254, 91, 254, 255, 2, 164, 189, 83, 5, 216, 161, 9, 8, 216, 57,
+    51, 72, 125, 157, 41, 83, 167, 237, 115,
+];
+
 /// Slice the length of the bytes array into 32bytes
 pub fn element_encoder(v: &[u8]) -> [u8; 32] {
     let mut output = [0u8; 32];
@@ -5,9 +21,48 @@ pub fn element_encoder(v: &[u8]) -> [u8; 32] {
     output
 }
 
-/// Truncate and pad 256 bit slice
-pub fn truncate_and_pad(t: &[u8]) -> Vec<u8> {
-    let mut truncated_bytes = t[..20].to_vec();
-    truncated_bytes.extend_from_slice(&[0u8; 12]);
-    truncated_bytes
+/// Whether the little endian bytes are a field element below the curve modulus
+pub fn is_canonical(element: &[u8; 32], curve: Curve) -> bool {
+    let modulus = match curve {
+        Curve::Bn254 => &BN254_MODULUS,
+        Curve::Bls381 => &BLS381_MODULUS,
+    };
+    // compare from the most significant byte
+    element.iter().rev().lt(modulus.iter().rev())
+}
+
+/// Sha256 digest, used to fingerprint verifying keys
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Version of the address encoding bound into withdraw proofs
+pub const ADDRESS_ENCODING_VERSION: u8 = 1;
+
+/// Encode the full address as `version || len_le_u32 || address bytes`
+pub fn encode_address(addr: &str) -> Vec<u8> {
+    let mut bytes = vec![ADDRESS_ENCODING_VERSION];
+    extend_length_prefixed(&mut bytes, addr.as_bytes());
+    bytes
+}
+
+/// Encode the deployment a proof is made for as `len_le_u32 || chain id || len_le_u32 || contract`
+pub fn encode_domain(chain_id: &str, contract_addr: &str) -> Vec<u8> {
+    let mut bytes = vec![];
+    extend_length_prefixed(&mut bytes, chain_id.as_bytes());
+    extend_length_prefixed(&mut bytes, contract_addr.as_bytes());
+    bytes
+}
+
+/// Encode the optional expiry height as `0` or `1 || height_le_u64`
+pub fn encode_expiry(valid_until_height: Option<u64>) -> Vec<u8> {
+    match valid_until_height {
+        Some(height) => [&[1u8][..], &height.to_le_bytes()].concat(),
+        None => vec![0u8],
+    }
+}
+
+fn extend_length_prefixed(bytes: &mut Vec<u8>, data: &[u8]) {
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
 }
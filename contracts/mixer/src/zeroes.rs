@@ -32,6 +32,7 @@
 
 pub const DEFAULT_LEAF: [u8; 32] = [0u8; 32];
 
+/// BN254 empty subtrees of the zero leaf, newer pools store their own for any curve
 pub fn zeroes(index: u32) -> [u8; 32] {
     match index {
         0 => DEFAULT_LEAF,
@@ -1,3 +1,4 @@
+use ark_bls12_381::Bls12_381;
 use ark_bn254::Bn254;
 use arkworks_setups::r1cs::mixer::MixerR1CSProver;
 use arkworks_setups::{Curve, MixerProver};
@@ -8,13 +9,22 @@ use node_bindgen::core::{
 use node_bindgen::derive::node_bindgen;
 use rand::rngs::OsRng;
 
+// the proof public input encoding is shared with the mixer contract
+use cosmwasm_mixer::utils::{encode_address, encode_domain, encode_expiry};
+
 type MixerR1CSProverBn254_30 = MixerR1CSProver<Bn254, 30>;
+type MixerR1CSProverBls381_30 = MixerR1CSProver<Bls12_381, 30>;
 const PK_BYTES: &[u8; 3034288] = include_bytes!("../../../bn254/x5/proving_key.bin");
+// empty leaf of a mixer instantiated without a zero leaf, not an extra public input
+const DEFAULT_LEAF: [u8; 32] = [0u8; 32];
 
-fn truncate_and_pad(t: &[u8]) -> Vec<u8> {
-    let mut truncated_bytes = t[..20].to_vec();
-    truncated_bytes.extend_from_slice(&[0u8; 12]);
-    truncated_bytes
+// curve names of the mixer instantiate msg, defaults to bn254
+fn parse_curve(curve: Option<String>) -> Result<Curve, NjError> {
+    match curve.as_deref() {
+        None | Some("bn254") => Ok(Curve::Bn254),
+        Some("bls381") => Ok(Curve::Bls381),
+        Some(curve) => Err(NjError::Other(format!("unsupported curve {}", curve))),
+    }
 }
 
 /// Create the zk preimage(proof, roots, nullifier, leaf)
@@ -27,6 +37,9 @@ fn setup_wasm_utils_zk_circuit(
     relayer: Vec<u8>,
     fee: u128,
     refund: u128,
+    zero_leaf: [u8; 32],
+    curve: Curve,
+    proving_key: Vec<u8>,
 ) -> Result<Vec<ArrayBuffer>, NjError> {
     let secret = note_secret[0..32].to_vec();
     let nullifier = note_secret[32..64].to_vec();
@@ -35,20 +48,36 @@ fn setup_wasm_utils_zk_circuit(
 
     let mut rng = OsRng;
 
-    let proof = MixerR1CSProverBn254_30::create_proof(
-        Curve::Bn254,
-        secret,
-        nullifier,
-        leaves_vec,
-        index as u64,
-        recipient,
-        relayer,
-        fee,
-        refund,
-        PK_BYTES.to_vec(),
-        [0u8; 32],
-        &mut rng,
-    )
+    let proof = match curve {
+        Curve::Bn254 => MixerR1CSProverBn254_30::create_proof(
+            curve,
+            secret,
+            nullifier,
+            leaves_vec,
+            index as u64,
+            recipient,
+            relayer,
+            fee,
+            refund,
+            proving_key,
+            zero_leaf,
+            &mut rng,
+        ),
+        Curve::Bls381 => MixerR1CSProverBls381_30::create_proof(
+            curve,
+            secret,
+            nullifier,
+            leaves_vec,
+            index as u64,
+            recipient,
+            relayer,
+            fee,
+            refund,
+            proving_key,
+            zero_leaf,
+            &mut rng,
+        ),
+    }
     .map_err(|err| NjError::Other(err.to_string()))?;
 
     Ok(vec![
@@ -69,17 +98,27 @@ fn gen_note() -> Result<ArrayBuffer, NjError> {
 }
 
 #[node_bindgen]
-fn gen_commitment(note_secret: JSArrayBuffer) -> ArrayBuffer {
+fn gen_commitment(
+    note_secret: JSArrayBuffer,
+    curve: Option<String>,
+) -> Result<ArrayBuffer, NjError> {
     let secret = note_secret[0..32].to_vec();
     let nullifier = note_secret[32..64].to_vec();
-    let leaf = MixerR1CSProverBn254_30::create_leaf_with_privates(
-        Curve::Bn254,
-        secret.to_vec(),
-        nullifier.to_vec(),
-    )
-    .unwrap();
+    let leaf = match parse_curve(curve)? {
+        Curve::Bn254 => MixerR1CSProverBn254_30::create_leaf_with_privates(
+            Curve::Bn254,
+            secret.to_vec(),
+            nullifier.to_vec(),
+        ),
+        Curve::Bls381 => MixerR1CSProverBls381_30::create_leaf_with_privates(
+            Curve::Bls381,
+            secret.to_vec(),
+            nullifier.to_vec(),
+        ),
+    }
+    .map_err(|err| NjError::Other(err.to_string()))?;
 
-    ArrayBuffer::new(leaf.leaf_bytes)
+    Ok(ArrayBuffer::new(leaf.leaf_bytes))
 }
 
 #[node_bindgen]
@@ -89,20 +128,57 @@ fn gen_zk(
     leaves: Vec<JSArrayBuffer>,
     recipient_addr: String,
     relayer_addr: String,
+    chain_id: String,
+    contract_addr: String,
     fee: Option<String>,
     refund: Option<String>,
+    valid_until_height: Option<String>,
+    zero_leaf: Option<JSArrayBuffer>,
+    curve: Option<String>,
+    proving_key: Option<JSArrayBuffer>,
 ) -> Result<Vec<ArrayBuffer>, NjError> {
     let fee_value = u128::from_str_radix(fee.unwrap_or_default().as_str(), 10).unwrap_or(0);
     let refund_value = u128::from_str_radix(refund.unwrap_or_default().as_str(), 10).unwrap_or(0);
+    // the proof can not be used after this height, the same value goes in the withdraw msg
+    let valid_until_height = valid_until_height
+        .map(|height| height.parse::<u64>())
+        .transpose()
+        .map_err(|err| NjError::Other(err.to_string()))?;
+    // the empty leaf of the mixer tree, reported by its config query
+    let zero_leaf = match zero_leaf {
+        Some(zero_leaf) => <[u8; 32]>::try_from(zero_leaf.to_vec().as_slice())
+            .map_err(|_| NjError::Other("zero leaf must be 32 bytes".to_string()))?,
+        None => DEFAULT_LEAF,
+    };
+    let curve = parse_curve(curve)?;
+    // only the bn254 proving key is bundled
+    let proving_key = match (curve, proving_key) {
+        (_, Some(proving_key)) => proving_key.to_vec(),
+        (Curve::Bn254, None) => PK_BYTES.to_vec(),
+        (Curve::Bls381, None) => {
+            return Err(NjError::Other(
+                "a proving key is required for bls381".to_string(),
+            ))
+        }
+    };
 
     // Setup zk circuit for withdraw
     setup_wasm_utils_zk_circuit(
         note_secret,
         index,
         leaves,
-        truncate_and_pad(recipient_addr.as_bytes()),
-        truncate_and_pad(relayer_addr.as_bytes()),
+        encode_address(&recipient_addr),
+        // the mixer checks the proof against its own chain id and address
+        [
+            encode_address(&relayer_addr),
+            encode_domain(&chain_id, &contract_addr),
+            encode_expiry(valid_until_height),
+        ]
+        .concat(),
         fee_value,
         refund_value,
+        zero_leaf,
+        curve,
+        proving_key,
     )
 }
@@ -1,6 +1,5 @@
 import 'dotenv/config';
 import * as cosmwasmMixer from './wasm/mixer_js/pkg';
-import axios from 'axios';
 import { DirectSecp256k1HdWallet } from '@cosmjs/proto-signing';
 import * as cosmwasm from '@cosmjs/cosmwasm-stargate';
 import { Decimal } from '@cosmjs/math';
@@ -11,6 +10,7 @@ let denom = process.env.DENOM;
 
 const recipient = 'orai1602dkqjvh4s7ryajnz2uwhr8vetrwr8nekpxv5';
 const contract_address = process.env.MIXER_ADDRESS;
+const pool_id = Number(process.env.POOL_ID ?? 0);
 // for (let i = 0; i < 10; i++) {
 //   console.log(
 //     Buffer.from(cosmwasmMixer.gen_note()).toString('base64').replace(/=+$/g, '')
@@ -37,10 +37,21 @@ function compare(a: Uint8Array, b: Uint8Array) {
 }
 
 let leaves: Uint8Array[] = undefined;
-const getLeaves = async (address: string): Promise<Uint8Array[]> => {
+// page through the leaves stored by the contract, no indexer needed
+const getLeaves = async (client: cosmwasm.SigningCosmWasmClient, address: string): Promise<Uint8Array[]> => {
   if (!leaves) {
-    const res = await axios.get(`${process.env.URL}/cosmos/tx/v1beta1/txs?events=wasm-mixer-deposit._contract_address%3d%27${address}%27`);
-    leaves = res.data.txs.map((tx: any) => Buffer.from(tx.body.messages[0].msg.deposit.commitment, 'base64'));
+    leaves = [];
+    let start_after: number = undefined;
+    while (true) {
+      const { commitments } = await client.queryContractSmart(address, {
+        commitments: { pool_id, start_after, limit: 1000 }
+      });
+      if (!commitments.length) break;
+      for (const { index, commitment } of commitments) {
+        leaves.push(Buffer.from(commitment, 'base64'));
+        start_after = index;
+      }
+    }
   }
   return leaves;
 };
@@ -55,11 +66,17 @@ const query = async (client: cosmwasm.SigningCosmWasmClient, address: string, in
   }
 };
 
-const getProof = async (sender: string, noteSecret: Uint8Array, recipient: string): Promise<Uint8Array[]> => {
+const getProof = async (client: cosmwasm.SigningCosmWasmClient, sender: string, noteSecret: Uint8Array, recipient: string): Promise<Uint8Array[]> => {
   const commitment_hash = cosmwasmMixer.gen_commitment(noteSecret);
-  const leaves = await getLeaves(contract_address);
+  const leaves = await getLeaves(client, contract_address);
   const leafIndex = leaves.findIndex((leaf) => compare(leaf, commitment_hash));
-  return cosmwasmMixer.gen_zk(noteSecret, leafIndex, leaves, recipient, sender);
+  const chainId = await client.getChainId();
+  // the prover has to build the tree from the same empty leaf
+  const { zero_leaf } = await query(client, contract_address, {
+    config: { pool_id }
+  });
+  const zeroLeaf = Buffer.from(zero_leaf, 'base64');
+  return cosmwasmMixer.gen_zk(noteSecret, leafIndex, leaves, recipient, sender, chainId, contract_address, undefined, undefined, undefined, zeroLeaf);
 };
 
 const runDeposit = async (client: cosmwasm.SigningCosmWasmClient, sender: string, index = 0) => {
@@ -67,7 +84,7 @@ const runDeposit = async (client: cosmwasm.SigningCosmWasmClient, sender: string
   const commitment_hash = cosmwasmMixer.gen_commitment(noteSecret);
 
   const { deposit_size } = await query(client, contract_address, {
-    config: {}
+    config: { pool_id }
   });
 
   const result = await client.execute(
@@ -75,6 +92,7 @@ const runDeposit = async (client: cosmwasm.SigningCosmWasmClient, sender: string
     contract_address,
     {
       deposit: {
+        pool_id,
         commitment: Buffer.from(commitment_hash).toString('base64')
       }
     },
@@ -87,7 +105,7 @@ const runDeposit = async (client: cosmwasm.SigningCosmWasmClient, sender: string
 };
 
 const runWithdraw = async (client: cosmwasm.SigningCosmWasmClient, sender: string, recipient: string, index = 0) => {
-  const [proof, root_hash, nullifier_hash] = await getProof(sender, noteSecrets[index], recipient);
+  const [proof, root_hash, nullifier_hash] = await getProof(client, sender, noteSecrets[index], recipient);
 
   // withdraw to this recipient
   const result = await client.execute(
@@ -95,6 +113,7 @@ const runWithdraw = async (client: cosmwasm.SigningCosmWasmClient, sender: strin
     contract_address,
     {
       withdraw: {
+        pool_id,
         proof_bytes: Buffer.from(proof).toString('base64'),
         root: Buffer.from(root_hash).toString('base64'),
         nullifier_hash: Buffer.from(nullifier_hash).toString('base64'),
@@ -1,3 +1,4 @@
+use ark_bls12_381::Bls12_381;
 use ark_bn254::Bn254;
 use arkworks_setups::r1cs::mixer::MixerR1CSProver;
 use arkworks_setups::{Curve, MixerProver};
@@ -5,13 +6,22 @@ use js_sys::Uint8Array;
 use rand::rngs::OsRng;
 use wasm_bindgen::prelude::*;
 
+// the proof public input encoding is shared with the mixer contract
+pub use cosmwasm_mixer::utils::{encode_address, encode_domain, encode_expiry};
+
 type MixerR1CSProverBn254_30 = MixerR1CSProver<Bn254, 30>;
+type MixerR1CSProverBls381_30 = MixerR1CSProver<Bls12_381, 30>;
 const PK_BYTES: &[u8; 3034288] = include_bytes!("../../../bn254/x5/proving_key.bin");
-
-pub fn truncate_and_pad(t: &[u8]) -> Vec<u8> {
-    let mut truncated_bytes = t[..20].to_vec();
-    truncated_bytes.extend_from_slice(&[0u8; 12]);
-    truncated_bytes
+// empty leaf of a mixer instantiated without a zero leaf, not an extra public input
+const DEFAULT_LEAF: [u8; 32] = [0u8; 32];
+
+// curve names of the mixer instantiate msg, defaults to bn254
+fn parse_curve(curve: Option<String>) -> Result<Curve, JsError> {
+    match curve.as_deref() {
+        None | Some("bn254") => Ok(Curve::Bn254),
+        Some("bls381") => Ok(Curve::Bls381),
+        Some(curve) => Err(JsError::new(&format!("unsupported curve {}", curve))),
+    }
 }
 
 // this method use macro to copy fixed size array
@@ -31,6 +41,9 @@ fn setup_wasm_utils_zk_circuit(
     relayer: Vec<u8>,
     fee: u128,
     refund: u128,
+    zero_leaf: [u8; 32],
+    curve: Curve,
+    proving_key: Vec<u8>,
 ) -> Result<Vec<Uint8Array>, JsError> {
     let secret = note_secret.slice(0, 32).to_vec();
     let nullifier = note_secret.slice(32, 64).to_vec();
@@ -39,20 +52,36 @@ fn setup_wasm_utils_zk_circuit(
 
     let mut rng = OsRng;
 
-    let proof = MixerR1CSProverBn254_30::create_proof(
-        Curve::Bn254,
-        secret,
-        nullifier,
-        leaves_vec,
-        index as u64,
-        recipient,
-        relayer,
-        fee,
-        refund,
-        PK_BYTES.to_vec(),
-        [0u8; 32],
-        &mut rng,
-    )
+    let proof = match curve {
+        Curve::Bn254 => MixerR1CSProverBn254_30::create_proof(
+            curve,
+            secret,
+            nullifier,
+            leaves_vec,
+            index as u64,
+            recipient,
+            relayer,
+            fee,
+            refund,
+            proving_key,
+            zero_leaf,
+            &mut rng,
+        ),
+        Curve::Bls381 => MixerR1CSProverBls381_30::create_proof(
+            curve,
+            secret,
+            nullifier,
+            leaves_vec,
+            index as u64,
+            recipient,
+            relayer,
+            fee,
+            refund,
+            proving_key,
+            zero_leaf,
+            &mut rng,
+        ),
+    }
     .map_err(|err| JsError::new(&err.to_string()))?;
 
     Ok(vec![
@@ -73,17 +102,27 @@ pub fn gen_note() -> Option<Uint8Array> {
 }
 
 #[wasm_bindgen]
-pub fn gen_commitment(note_secret: Uint8Array) -> Uint8Array {
+pub fn gen_commitment(
+    note_secret: Uint8Array,
+    curve: Option<String>,
+) -> Result<Uint8Array, JsError> {
     let secret = note_secret.slice(0, 32).to_vec();
     let nullifier = note_secret.slice(32, 64).to_vec();
-    let leaf = MixerR1CSProverBn254_30::create_leaf_with_privates(
-        Curve::Bn254,
-        secret.to_vec(),
-        nullifier.to_vec(),
-    )
-    .unwrap();
+    let leaf = match parse_curve(curve)? {
+        Curve::Bn254 => MixerR1CSProverBn254_30::create_leaf_with_privates(
+            Curve::Bn254,
+            secret.to_vec(),
+            nullifier.to_vec(),
+        ),
+        Curve::Bls381 => MixerR1CSProverBls381_30::create_leaf_with_privates(
+            Curve::Bls381,
+            secret.to_vec(),
+            nullifier.to_vec(),
+        ),
+    }
+    .map_err(|err| JsError::new(&err.to_string()))?;
 
-    from_bytes(&leaf.leaf_bytes, Some(32))
+    Ok(from_bytes(&leaf.leaf_bytes, Some(32)))
 }
 
 #[wasm_bindgen]
@@ -93,21 +132,54 @@ pub fn gen_zk(
     leaves: Vec<Uint8Array>,
     recipient_addr: String,
     relayer_addr: String,
+    chain_id: String,
+    contract_addr: String,
     fee: Option<String>,
     refund: Option<String>,
+    valid_until_height: Option<String>,
+    zero_leaf: Option<Uint8Array>,
+    curve: Option<String>,
+    proving_key: Option<Uint8Array>,
 ) -> Result<Vec<Uint8Array>, JsError> {
     let fee_value = u128::from_str_radix(fee.unwrap_or_default().as_str(), 10).unwrap_or(0);
     let refund_value = u128::from_str_radix(refund.unwrap_or_default().as_str(), 10).unwrap_or(0);
+    // the proof can not be used after this height, the same value goes in the withdraw msg
+    let valid_until_height = valid_until_height
+        .map(|height| height.parse::<u64>())
+        .transpose()
+        .map_err(|err| JsError::new(&err.to_string()))?;
+    // the empty leaf of the mixer tree, reported by its config query
+    let zero_leaf = match zero_leaf {
+        Some(zero_leaf) => <[u8; 32]>::try_from(zero_leaf.to_vec().as_slice())
+            .map_err(|_| JsError::new("zero leaf must be 32 bytes"))?,
+        None => DEFAULT_LEAF,
+    };
+    let curve = parse_curve(curve)?;
+    // only the bn254 proving key is bundled
+    let proving_key = match (curve, proving_key) {
+        (_, Some(proving_key)) => proving_key.to_vec(),
+        (Curve::Bn254, None) => PK_BYTES.to_vec(),
+        (Curve::Bls381, None) => return Err(JsError::new("a proving key is required for bls381")),
+    };
 
     // Setup zk circuit for withdraw
     setup_wasm_utils_zk_circuit(
         note_secret,
         index,
         leaves,
-        truncate_and_pad(recipient_addr.as_bytes()),
-        truncate_and_pad(relayer_addr.as_bytes()),
+        encode_address(&recipient_addr),
+        // the mixer checks the proof against its own chain id and address
+        [
+            encode_address(&relayer_addr),
+            encode_domain(&chain_id, &contract_addr),
+            encode_expiry(valid_until_height),
+        ]
+        .concat(),
         fee_value,
         refund_value,
+        zero_leaf,
+        curve,
+        proving_key,
     )
 }
 
@@ -122,7 +194,7 @@ mod tests {
         let mut leaves = vec![];
         let recipient = "orai1602dkqjvh4s7ryajnz2uwhr8vetrwr8nekpxv5";
         let note_secret = hex::decode("1d7a0858c98d688d9bb71cce07607a518ecb22b80def55c820335d972196536c193a0f66b049617cc045306cad05e9956352a25c98159b8273c6449b96047dcd").unwrap();
-        let commitment_hash = gen_commitment(from_bytes(&note_secret, None));
+        let commitment_hash = gen_commitment(from_bytes(&note_secret, None), None).unwrap();
         leaves.push(commitment_hash);
         let now = Date::now();
         let success = gen_zk(
@@ -131,6 +203,12 @@ mod tests {
             leaves,
             recipient.to_string(),
             recipient.to_string(),
+            "Oraichain".to_string(),
+            "orai1mixer".to_string(),
+            None,
+            None,
+            None,
+            None,
             None,
             None,
         )